@@ -0,0 +1,8 @@
+#![windows_subsystem = "windows"]
+
+use equicord_launcher::discord::DiscordBranch;
+
+#[tokio::main]
+async fn main() {
+    equicord_launcher::launch(DiscordBranch::Stable).await;
+}
@@ -1,12 +1,10 @@
 #![windows_subsystem = "windows"]
 
-use equicord_launcher::discord::DiscordBranch;
+//! Alias for `equicord --branch stable`, kept for existing shortcuts.
 
-static INSTANCE_ID: &str = "EquicordStable";
-static DISCORD_BRANCH: DiscordBranch = DiscordBranch::Stable;
-static DISPLAY_NAME: &str = "Discord Stable";
+use equicord_launcher::discord::DiscordBranch;
 
 #[tokio::main]
 async fn main() {
-    equicord_launcher::launch(INSTANCE_ID, DISCORD_BRANCH, DISPLAY_NAME).await;
+    equicord_launcher::launch(DiscordBranch::Stable).await;
 }
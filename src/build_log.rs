@@ -0,0 +1,285 @@
+//! Persisted logs of custom builds.
+//!
+//! Every custom build streams the output of its subprocesses to the console and to
+//! `logs/build.log` in the launcher data directory. The logs of the previous few
+//! builds are kept as `build.1.log`, `build.2.log`, and so on.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+
+use crate::{constants, events, logging, process};
+
+/// How many build logs are kept, including the current one.
+const MAX_BUILD_LOGS: usize = 5;
+
+fn log_path(logs_dir: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        logs_dir.join(constants::BUILD_LOG_FILE)
+    } else {
+        logs_dir.join(format!("build.{index}.log"))
+    }
+}
+
+/// The most recent build log, if any build has run yet.
+pub fn latest_build_log() -> Option<PathBuf> {
+    let path = log_path(&constants::logs_dir()?, 0);
+    path.is_file().then_some(path)
+}
+
+/// A calendar date and time in UTC.
+#[derive(Clone, Copy, Debug)]
+pub struct UtcTime {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl UtcTime {
+    pub fn now() -> UtcTime {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+        // Convert days since the epoch to a civil date, see
+        // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        UtcTime {
+            year: year as u32,
+            month: month as u32,
+            day: day as u32,
+            hour: (secs_of_day / 3600) as u32,
+            minute: (secs_of_day % 3600 / 60) as u32,
+            second: (secs_of_day % 60) as u32,
+        }
+    }
+}
+
+/// Format the current time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn utc_timestamp() -> String {
+    let now = UtcTime::now();
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        now.year, now.month, now.day, now.hour, now.minute, now.second
+    )
+}
+
+/// The program and arguments of `cmd`, separated by spaces.
+pub(crate) fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The log of a single custom build.
+pub struct BuildLog {
+    path: Option<PathBuf>,
+    file: Mutex<Option<File>>,
+}
+
+impl BuildLog {
+    /// Rotate the existing logs and start a new one.
+    ///
+    /// If the log file can't be created, output is still streamed to the console.
+    pub fn create() -> BuildLog {
+        let file = constants::logs_dir().and_then(|logs_dir| {
+            std::fs::create_dir_all(&logs_dir).ok()?;
+
+            let _ = std::fs::remove_file(log_path(&logs_dir, MAX_BUILD_LOGS - 1));
+            for index in (0..MAX_BUILD_LOGS - 1).rev() {
+                let _ = std::fs::rename(log_path(&logs_dir, index), log_path(&logs_dir, index + 1));
+            }
+
+            let path = log_path(&logs_dir, 0);
+            let file = File::create(&path).ok()?;
+            Some((path, file))
+        });
+
+        let (path, file) = match file {
+            Some((path, file)) => (Some(path), Some(file)),
+            None => {
+                warn!("Failed to create the build log file.");
+                (None, None)
+            }
+        };
+
+        let log = BuildLog {
+            path,
+            file: Mutex::new(file),
+        };
+
+        log.write_line(&format!(
+            "Equicord Launcher {} custom build, started {}",
+            env!("CARGO_PKG_VERSION"),
+            utc_timestamp()
+        ));
+
+        log
+    }
+
+    /// Path of the log file, if it could be created.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Write a line to the log file only.
+    pub fn write_line(&self, line: &str) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = writeln!(file, "{line}");
+            }
+        }
+    }
+
+    /// Read lines from a subprocess pipe, echoing them to the console unless quieted,
+    /// and always to the log file.
+    fn stream(&self, pipe: impl Read, captured: Option<&Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+
+        while reader
+            .read_until(b'\n', &mut buf)
+            .is_ok_and(|read| read > 0)
+        {
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if logging::echo_subprocess_output() {
+                // stdout is reserved for events when they are enabled
+                if events::enabled() {
+                    eprintln!("{line}");
+                } else {
+                    println!("{line}");
+                }
+            }
+            self.write_line(line);
+
+            if let Some(Ok(mut captured)) = captured.map(|c| c.lock()) {
+                captured.push(line.to_string());
+            }
+
+            buf.clear();
+        }
+    }
+
+    /// Run a command to completion, streaming its output line by line.
+    ///
+    /// `what` names the command in error messages, e.g. `pnpm build`. The output itself
+    /// isn't part of the error, it's in the console and the log file.
+    ///
+    /// The command and everything it spawned are killed if it runs longer than `timeout`
+    /// or Ctrl-C is pressed, see [`process::CancelScope`].
+    pub fn run(
+        &self,
+        cmd: &mut Command,
+        what: &str,
+        timeout: Option<Duration>,
+    ) -> Result<(), String> {
+        self.run_inner(cmd, what, timeout, None)
+    }
+
+    /// Like [`run`](Self::run), also collecting the command's output lines into `output`,
+    /// whether it succeeds or not.
+    pub fn run_capturing(
+        &self,
+        cmd: &mut Command,
+        what: &str,
+        timeout: Option<Duration>,
+        output: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let captured = Mutex::new(Vec::new());
+        let result = self.run_inner(cmd, what, timeout, Some(&captured));
+        output.extend(captured.into_inner().unwrap_or_default());
+        result
+    }
+
+    fn run_inner(
+        &self,
+        cmd: &mut Command,
+        what: &str,
+        timeout: Option<Duration>,
+        captured: Option<&Mutex<Vec<String>>>,
+    ) -> Result<(), String> {
+        if process::cancel_requested() {
+            return Err(format!("{what} was cancelled."));
+        }
+
+        let command_line = command_line(cmd);
+        debug!("Running {command_line}");
+        self.write_line(&format!("$ {command_line}"));
+
+        // Run in a process group of its own so the whole tree can be killed
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(cmd, 0);
+
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run {what}: {e}"))?;
+
+        let started = Instant::now();
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        let status = std::thread::scope(|scope| {
+            if let Some(stdout) = stdout {
+                scope.spawn(|| self.stream(stdout, captured));
+            }
+            if let Some(stderr) = stderr {
+                scope.spawn(|| self.stream(stderr, captured));
+            }
+
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => return Ok(status),
+                    Ok(None) => {}
+                    Err(e) => return Err(format!("Failed to wait for {what}: {e}")),
+                }
+
+                let error = if process::cancel_requested() {
+                    format!("{what} was cancelled.")
+                } else if let Some(timeout) = timeout.filter(|t| started.elapsed() > *t) {
+                    format!("{what} timed out after {}s.", timeout.as_secs())
+                } else {
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                };
+
+                process::kill_process_tree(child.id());
+                let _ = child.wait();
+                return Err(error);
+            }
+        })?;
+
+        if !status.success() {
+            return Err(format!("{what} failed ({status})."));
+        }
+
+        Ok(())
+    }
+}
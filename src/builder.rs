@@ -3,9 +3,10 @@
 //! Handles cloning/updating the Equicord repo, syncing userplugins,
 //! and running the build process.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
@@ -14,35 +15,86 @@ use std::os::windows::process::CommandExt;
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
 /// Create a Command that won't open a visible console window on Windows.
-fn silent_cmd(program: &str) -> Command {
+pub(crate) fn silent_cmd(program: &str) -> Command {
+    #[allow(unused_mut)]
     let mut cmd = Command::new(program);
     #[cfg(windows)]
     cmd.creation_flags(CREATE_NO_WINDOW);
     cmd
 }
 
+use log::{info, warn};
+use sha2::{Digest, Sha256};
 use tinyjson::JsonValue;
 
+use crate::build_log::BuildLog;
 use crate::constants;
-
-#[cfg(windows)]
-use crate::progress::ProgressWindow;
+use crate::dry_run::Plan;
+use crate::package_manager::PackageManager;
+use crate::patches::{
+    apply_command, apply_patches, find_patches, has_local_changes, hash_patches, restore_commands,
+    restore_repo,
+};
+use crate::process::{self, CancelScope};
+use crate::reporter;
+use crate::sources::UserpluginSource;
+use crate::userplugins::{
+    check_userplugins, hash_directories, peek_directories_hash, sync_userplugins, to_hex,
+};
+use crate::version::{Version, VersionReq};
 
 const TOTAL_STEPS: u32 = 6;
 
-/// Check that git, node, and pnpm are available on PATH.
-fn check_prerequisites() -> Result<(), String> {
+/// The build tools found by [`check_prerequisites`].
+struct Toolchain {
+    node: Option<Version>,
+    package_manager: PackageManager,
+    package_manager_version: Option<Version>,
+}
+
+/// Fail with a message naming the installed and required versions if `installed` doesn't match.
+fn require_version(
+    tool: &str,
+    installed: &Version,
+    required: &VersionReq,
+    origin: &str,
+) -> Result<(), String> {
+    if required.matches(installed) {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{tool} {installed} is installed, but {required} is required ({origin}).\n\
+        Please update {tool} and try again."
+    ))
+}
+
+/// Check that git, node, and a package manager are available on PATH
+/// and meet the launcher's minimum versions.
+fn check_prerequisites(package_manager: Option<PackageManager>) -> Result<Toolchain, String> {
     let commands = [
-        ("git", &["--version"][..]),
-        ("node", &["--version"]),
-        ("pnpm", &["--version"]),
+        ("git", "git", constants::MIN_GIT_VERSION),
+        ("node", "Node.js", constants::MIN_NODE_VERSION),
     ];
+    let mut node = None;
 
-    for (cmd, args) in &commands {
-        match silent_cmd(cmd).args(*args).output() {
+    for (cmd, name, minimum) in commands {
+        match silent_cmd(cmd).arg("--version").output() {
             Ok(output) if output.status.success() => {
                 let version = String::from_utf8_lossy(&output.stdout);
-                println!("[Equicord Launcher] Found {}: {}", cmd, version.trim());
+                info!("Found {}: {}", cmd, version.trim());
+
+                let Some(version) = Version::find_in(&version) else {
+                    warn!("couldn't parse the {name} version, skipping the version check.");
+                    continue;
+                };
+
+                let minimum = VersionReq::at_least(Version::parse(minimum)?);
+                require_version(name, &version, &minimum, "launcher minimum")?;
+
+                if cmd == "node" {
+                    node = Some(version);
+                }
             }
             Ok(output) => {
                 let stderr = String::from_utf8_lossy(&output.stderr);
@@ -55,75 +107,181 @@ fn check_prerequisites() -> Result<(), String> {
             Err(_) => {
                 return Err(format!(
                     "'{}' is not installed or not in PATH.\n\
-                    The --custom flag requires git, Node.js (>=18), and pnpm.\n\
+                    The --custom flag requires git (>={}), Node.js (>={}), and one of pnpm, npm, yarn or bun.\n\
                     Please install them and try again.",
-                    cmd
+                    cmd,
+                    constants::MIN_GIT_VERSION,
+                    constants::MIN_NODE_VERSION
                 ));
             }
         }
     }
 
-    Ok(())
+    let (package_manager, version) = PackageManager::resolve(package_manager)?;
+    info!("Found {package_manager}: {version}");
+
+    let package_manager_version = Version::find_in(&version);
+
+    if let (PackageManager::Pnpm | PackageManager::CorepackPnpm, Some(version)) =
+        (package_manager, &package_manager_version)
+    {
+        let minimum = VersionReq::at_least(Version::parse(constants::MIN_PNPM_VERSION)?);
+        require_version("pnpm", version, &minimum, "launcher minimum")?;
+    }
+
+    Ok(Toolchain {
+        node,
+        package_manager,
+        package_manager_version,
+    })
 }
 
-/// Clone the Equicord repo, or pull latest if it already exists.
-fn clone_or_update_repo(repo_dir: &Path) -> Result<(), String> {
-    if repo_dir.join(".git").exists() {
-        println!("[Equicord Launcher] Updating Equicord repository...");
+/// Check the toolchain against the `engines` and `packageManager` fields of Equicord's package.json.
+fn check_engine_requirements(repo_dir: &Path, toolchain: &Toolchain) -> Result<(), String> {
+    let package_json = repo_dir.join("package.json");
+    let data = std::fs::read_to_string(&package_json)
+        .map_err(|e| format!("Failed to read {:?}: {e}", package_json))?;
+    let json: JsonValue = data
+        .parse()
+        .map_err(|e| format!("Failed to parse {:?}: {e:?}", package_json))?;
+    let Some(object) = json.get::<HashMap<String, JsonValue>>() else {
+        return Ok(());
+    };
 
-        // Fetch and reset to origin/main to handle force pushes on rolling releases
-        let fetch = silent_cmd("git")
-            .args(["fetch", "origin", "main"])
-            .current_dir(repo_dir)
-            .output()
-            .map_err(|e| format!("Failed to run git fetch: {e}"))?;
+    let engines = object
+        .get("engines")
+        .and_then(|engines| engines.get::<HashMap<String, JsonValue>>());
+    // A range we can't read shouldn't stop the build, the tools may well be fine
+    let engine = |name: &str| -> Option<VersionReq> {
+        let range = engines?.get(name)?.get::<String>()?;
+        VersionReq::parse(range)
+            .inspect_err(|e| warn!("Skipping the engines.{name} check: {e}"))
+            .ok()
+    };
 
-        if !fetch.status.success() {
-            let stderr = String::from_utf8_lossy(&fetch.stderr);
-            return Err(format!("git fetch failed: {stderr}"));
-        }
+    if let (Some(node), Some(required)) = (&toolchain.node, engine("node")) {
+        require_version(
+            "Node.js",
+            node,
+            &required,
+            "engines.node in Equicord's package.json",
+        )?;
+    }
 
-        let reset = silent_cmd("git")
-            .args(["reset", "--hard", "origin/main"])
-            .current_dir(repo_dir)
-            .output()
-            .map_err(|e| format!("Failed to run git reset: {e}"))?;
+    // Corepack switches to the requested pnpm version by itself, and the
+    // pnpm-specific fields don't apply to other package managers
+    if toolchain.package_manager != PackageManager::Pnpm {
+        return Ok(());
+    }
 
-        if !reset.status.success() {
-            let stderr = String::from_utf8_lossy(&reset.stderr);
-            return Err(format!("git reset failed: {stderr}"));
-        }
+    let Some(pnpm) = &toolchain.package_manager_version else {
+        return Ok(());
+    };
+
+    if let Some(required) = engine("pnpm") {
+        require_version(
+            "pnpm",
+            pnpm,
+            &required,
+            "engines.pnpm in Equicord's package.json",
+        )?;
+    }
+
+    // e.g. "pnpm@10.4.1" or "pnpm@10.4.1+sha512.abc..."
+    let requested = object
+        .get("packageManager")
+        .and_then(|v| v.get::<String>())
+        .and_then(|v| v.strip_prefix("pnpm@"));
+
+    let required = requested.and_then(|requested| {
+        VersionReq::parse(&format!("^{requested}"))
+            .inspect_err(|e| warn!("Skipping the packageManager check: {e}"))
+            .ok()
+    });
+
+    if let Some(required) = required {
+        require_version(
+            "pnpm",
+            pnpm,
+            &required,
+            "packageManager in Equicord's package.json",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Clone the Equicord repo, or pull latest if it already exists.
+fn clone_or_update_repo(
+    repo_dir: &Path,
+    timeouts: &StepTimeouts,
+    log: &BuildLog,
+) -> Result<(), String> {
+    if repo_dir.join(".git").exists() {
+        info!("Updating Equicord repository...");
     } else {
-        println!("[Equicord Launcher] Cloning Equicord repository...");
+        info!("Cloning Equicord repository...");
 
         // Make sure parent directory exists
         if let Some(parent) = repo_dir.parent() {
             std::fs::create_dir_all(parent)
                 .map_err(|e| format!("Failed to create directory: {e}"))?;
         }
+    }
 
-        let clone = silent_cmd("git")
-            .args([
-                "clone",
-                "--depth",
-                "1",
-                "--branch",
-                "main",
-                constants::EQUICORD_REPO_URL,
-                &repo_dir.to_string_lossy(),
-            ])
-            .output()
-            .map_err(|e| format!("Failed to run git clone: {e}"))?;
-
-        if !clone.status.success() {
-            let stderr = String::from_utf8_lossy(&clone.stderr);
-            return Err(format!("git clone failed: {stderr}"));
-        }
+    for (mut cmd, what) in repo_update_commands(repo_dir) {
+        log.run(&mut cmd, what, timeouts.git)?;
     }
 
     Ok(())
 }
 
+/// The commands run by [`clone_or_update_repo`], with what they do.
+fn repo_update_commands(repo_dir: &Path) -> Vec<(Command, &'static str)> {
+    if repo_dir.join(".git").exists() {
+        // Fetch and reset to origin/main to handle force pushes on rolling releases
+        let mut fetch = silent_cmd("git");
+        fetch
+            .args(["fetch", "origin", "main"])
+            .current_dir(repo_dir);
+
+        let mut reset = silent_cmd("git");
+        reset
+            .args(["reset", "--hard", "origin/main"])
+            .current_dir(repo_dir);
+
+        vec![(fetch, "git fetch"), (reset, "git reset")]
+    } else {
+        let mut clone = silent_cmd("git");
+        clone.args([
+            "clone",
+            "--depth",
+            "1",
+            "--branch",
+            "main",
+            constants::EQUICORD_REPO_URL,
+            &repo_dir.to_string_lossy(),
+        ]);
+
+        vec![(clone, "git clone")]
+    }
+}
+
+/// The latest commit on Equicord's main branch, without fetching it.
+fn remote_git_hash() -> Result<String, String> {
+    let output = silent_cmd("git")
+        .args(["ls-remote", constants::EQUICORD_REPO_URL, "refs/heads/main"])
+        .output()
+        .map_err(|e| format!("Failed to query the Equicord repository: {e}"))?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .filter(|_| output.status.success())
+        .map(str::to_string)
+        .ok_or_else(|| "Failed to query the Equicord repository".to_string())
+}
+
 /// Get the current HEAD commit hash.
 fn get_git_hash(repo_dir: &Path) -> Result<String, String> {
     let output = silent_cmd("git")
@@ -139,106 +297,17 @@ fn get_git_hash(repo_dir: &Path) -> Result<String, String> {
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-/// Compute a simple hash of the userplugins directory based on file names, sizes, and mtimes.
-fn hash_directory(dir: &Path) -> Result<String, String> {
-    if !dir.exists() {
-        return Ok("empty".to_string());
-    }
-
-    let mut entries = Vec::new();
-    collect_dir_entries(dir, dir, &mut entries)?;
-    entries.sort();
-
-    // Simple hash: concatenate all entries and use their combined string
-    let combined = entries.join("|");
-    // Use a simple hash — we don't need cryptographic strength here
-    let hash = combined
-        .bytes()
-        .fold(0u64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64));
-
-    Ok(format!("{:016x}", hash))
-}
-
-fn collect_dir_entries(base: &Path, dir: &Path, entries: &mut Vec<String>) -> Result<(), String> {
-    let read_dir =
-        std::fs::read_dir(dir).map_err(|e| format!("Failed to read directory {:?}: {e}", dir))?;
-
-    for entry in read_dir {
-        let entry = entry.map_err(|e| format!("Failed to read dir entry: {e}"))?;
-        let path = entry.path();
-        let relative = path
-            .strip_prefix(base)
-            .unwrap_or(&path)
-            .to_string_lossy()
-            .to_string();
-
-        if path.is_dir() {
-            collect_dir_entries(base, &path, entries)?;
-        } else {
-            let metadata = entry
-                .metadata()
-                .map_err(|e| format!("Failed to read metadata: {e}"))?;
-            let size = metadata.len();
-            let modified = metadata
-                .modified()
-                .map(|t| {
-                    t.duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs()
-                })
-                .unwrap_or(0);
-            entries.push(format!("{}:{}:{}", relative, size, modified));
-        }
-    }
-
-    Ok(())
-}
-
-/// Clear and copy userplugins into the repo's src/userplugins/ directory.
-fn sync_userplugins(userplugins_src: &Path, repo_dir: &Path) -> Result<(), String> {
-    let dest = repo_dir.join("src").join("userplugins");
-
-    // Remove existing userplugins
-    if dest.exists() {
-        std::fs::remove_dir_all(&dest)
-            .map_err(|e| format!("Failed to clear userplugins dir: {e}"))?;
-    }
-
-    std::fs::create_dir_all(&dest).map_err(|e| format!("Failed to create userplugins dir: {e}"))?;
-
-    // Copy all files/dirs from source to dest
-    copy_dir_recursive(userplugins_src, &dest)?;
-
-    let count = std::fs::read_dir(&dest).map(|d| d.count()).unwrap_or(0);
-    println!("[Equicord Launcher] Synced {count} userplugin(s).");
-
-    Ok(())
-}
-
-fn copy_dir_recursive(src: &Path, dest: &Path) -> Result<(), String> {
-    let read_dir = std::fs::read_dir(src).map_err(|e| format!("Failed to read {:?}: {e}", src))?;
-
-    for entry in read_dir {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {e}"))?;
-        let src_path = entry.path();
-        let dest_path = dest.join(entry.file_name());
-
-        if src_path.is_dir() {
-            std::fs::create_dir_all(&dest_path)
-                .map_err(|e| format!("Failed to create dir {:?}: {e}", dest_path))?;
-            copy_dir_recursive(&src_path, &dest_path)?;
-        } else {
-            std::fs::copy(&src_path, &dest_path)
-                .map_err(|e| format!("Failed to copy {:?}: {e}", src_path))?;
-        }
-    }
-
-    Ok(())
-}
-
 struct BuildState {
     git_commit: String,
     userplugins_hash: String,
+    /// Hash of the dependency manifests `node_modules` was installed from.
+    dependencies_hash: String,
+    /// Hash of the patches applied to the repo, empty if there were none.
+    patches_hash: String,
+    /// Hash of the extra build flags, empty if there were none.
+    flags_hash: String,
+    /// Userplugins left out of the build because they failed to compile.
+    dropped_plugins: Vec<String>,
 }
 
 /// Load build state from disk.
@@ -250,71 +319,275 @@ fn load_build_state(cache_dir: &Path) -> Option<BuildState> {
 
     let git_commit: &String = object.get("git_commit")?.get()?;
     let userplugins_hash: &String = object.get("userplugins_hash")?.get()?;
+    // Missing in state files from older versions, which just means we reinstall once
+    let dependencies_hash: String = object
+        .get("dependencies_hash")
+        .and_then(|v| v.get::<String>())
+        .cloned()
+        .unwrap_or_default();
+    let patches_hash: String = object
+        .get("patches_hash")
+        .and_then(|v| v.get::<String>())
+        .cloned()
+        .unwrap_or_default();
+    let flags_hash: String = object
+        .get("flags_hash")
+        .and_then(|v| v.get::<String>())
+        .cloned()
+        .unwrap_or_default();
+    let dropped_plugins: Vec<String> = object
+        .get("dropped_plugins")
+        .and_then(|v| v.get::<Vec<JsonValue>>())
+        .map(|plugins| {
+            plugins
+                .iter()
+                .filter_map(|plugin| plugin.get::<String>().cloned())
+                .collect()
+        })
+        .unwrap_or_default();
 
     Some(BuildState {
         git_commit: git_commit.clone(),
         userplugins_hash: userplugins_hash.clone(),
+        dependencies_hash,
+        patches_hash,
+        flags_hash,
+        dropped_plugins,
     })
 }
 
 /// Save build state to disk.
-fn save_build_state(
-    cache_dir: &Path,
-    git_commit: &str,
-    userplugins_hash: &str,
-) -> Result<(), String> {
+fn save_build_state(cache_dir: &Path, state: &BuildState) -> Result<(), String> {
     let state_file = cache_dir.join(constants::CUSTOM_BUILD_STATE_FILE);
+    let dropped_plugins = JsonValue::Array(
+        state
+            .dropped_plugins
+            .iter()
+            .map(|plugin| JsonValue::String(plugin.clone()))
+            .collect(),
+    )
+    .stringify()
+    .map_err(|e| format!("Failed to serialize build state: {e}"))?;
     let json = format!(
         "{{\n\
-        \t\"git_commit\": \"{git_commit}\",\n\
-        \t\"userplugins_hash\": \"{userplugins_hash}\"\n\
-        }}"
+        \t\"git_commit\": \"{}\",\n\
+        \t\"userplugins_hash\": \"{}\",\n\
+        \t\"dependencies_hash\": \"{}\",\n\
+        \t\"patches_hash\": \"{}\",\n\
+        \t\"flags_hash\": \"{}\",\n\
+        \t\"dropped_plugins\": {}\n\
+        }}",
+        state.git_commit,
+        state.userplugins_hash,
+        state.dependencies_hash,
+        state.patches_hash,
+        state.flags_hash,
+        dropped_plugins
     );
     std::fs::write(&state_file, json).map_err(|e| format!("Failed to write build state: {e}"))
 }
 
-/// Run pnpm install in the repo directory.
-fn run_pnpm_install(repo_dir: &Path) -> Result<(), String> {
-    println!("[Equicord Launcher] Running pnpm install...");
+/// Hash the files that determine the contents of `node_modules`,
+/// along with the package manager that installs them.
+fn hash_dependency_manifests(
+    repo_dir: &Path,
+    package_manager: PackageManager,
+) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(package_manager.name().as_bytes());
+
+    for filename in constants::DEPENDENCY_MANIFESTS {
+        let path = repo_dir.join(filename);
+        let contents =
+            std::fs::read(&path).map_err(|e| format!("Failed to read {:?}: {e}", path))?;
+        hasher.update(b"\0");
+        hasher.update(filename.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(&contents);
+    }
 
-    let output = silent_cmd("pnpm")
-        .args(["install", "--frozen-lockfile"])
-        .current_dir(repo_dir)
-        .output()
-        .map_err(|e| format!("Failed to run pnpm install: {e}"))?;
+    Ok(to_hex(&hasher.finalize()))
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "pnpm install failed:\nstdout: {stdout}\nstderr: {stderr}"
-        ));
+/// The command installing Equicord's dependencies.
+fn install_command(repo_dir: &Path, toolchain: &Toolchain) -> Command {
+    let package_manager = toolchain.package_manager;
+    let mut cmd = package_manager.command();
+    cmd.args(package_manager.install_args(toolchain.package_manager_version.as_ref()))
+        .current_dir(repo_dir);
+    cmd
+}
+
+/// The lockfile installing leaves behind, which doesn't belong in Equicord's tree.
+fn leftover_lockfile(repo_dir: &Path, toolchain: &Toolchain) -> Option<PathBuf> {
+    toolchain
+        .package_manager
+        .leftover_lockfile(toolchain.package_manager_version.as_ref())
+        .map(|lockfile| repo_dir.join(lockfile))
+}
+
+/// The command running Equicord's build script with the options' arguments and environment.
+fn build_command(
+    repo_dir: &Path,
+    package_manager: PackageManager,
+    options: &BuildOptions,
+) -> Command {
+    let mut cmd = package_manager.command();
+    cmd.args(package_manager.build_args());
+
+    let script_args = options.script_args();
+    if !script_args.is_empty() {
+        cmd.args(package_manager.script_args_separator());
+        cmd.args(&script_args);
     }
 
-    println!("[Equicord Launcher] pnpm install complete.");
+    cmd.envs(options.build_env.iter().map(|(key, value)| (key, value)))
+        .current_dir(repo_dir);
+    cmd
+}
+
+/// Install Equicord's dependencies in the repo directory.
+fn run_install(
+    repo_dir: &Path,
+    toolchain: &Toolchain,
+    timeout: Option<Duration>,
+    log: &BuildLog,
+) -> Result<(), String> {
+    let package_manager = toolchain.package_manager;
+    info!("Running {package_manager} install...");
+
+    log.run(
+        &mut install_command(repo_dir, toolchain),
+        &format!("{package_manager} install"),
+        timeout,
+    )?;
+
+    if let Some(lockfile) = leftover_lockfile(repo_dir, toolchain) {
+        if let Err(e) = std::fs::remove_file(&lockfile) {
+            warn!("Failed to remove {}: {e}", lockfile.display());
+        }
+    }
+
+    info!("{package_manager} install complete.");
     Ok(())
 }
 
-/// Run pnpm build in the repo directory.
-fn run_pnpm_build(repo_dir: &Path) -> Result<(), String> {
-    println!("[Equicord Launcher] Running pnpm build...");
+/// Run Equicord's build script in the repo directory.
+///
+/// The output is collected into `output` so failures can be traced back to a userplugin.
+fn run_build(
+    repo_dir: &Path,
+    package_manager: PackageManager,
+    options: &BuildOptions,
+    log: &BuildLog,
+    output: &mut Vec<String>,
+) -> Result<(), String> {
+    info!("Running {package_manager} build...");
 
-    let output = silent_cmd("pnpm")
-        .args(["build"])
-        .current_dir(repo_dir)
-        .output()
-        .map_err(|e| format!("Failed to run pnpm build: {e}"))?;
+    log.run_capturing(
+        &mut build_command(repo_dir, package_manager, options),
+        &format!("{package_manager} build"),
+        options.timeouts.build,
+        output,
+    )?;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "pnpm build failed:\nstdout: {stdout}\nstderr: {stderr}"
-        ));
+    info!("{package_manager} build complete.");
+    Ok(())
+}
+
+/// The userplugin a line of build output refers to, if any.
+fn userplugin_in(line: &str) -> Option<String> {
+    ["src/userplugins/", "src\\userplugins\\"]
+        .iter()
+        .find_map(|prefix| line.split_once(prefix))
+        .and_then(|(_, rest)| rest.split(['/', '\\', ':']).next())
+        .filter(|plugin| !plugin.is_empty())
+        .map(|plugin| plugin.to_string())
+}
+
+/// Find the userplugins that esbuild reported errors in, along with the first error of each.
+///
+/// Understands both esbuild's pretty `✘ [ERROR] message` blocks followed by the location,
+/// and the one-line `file:line:column: ERROR: message` summary.
+fn failing_userplugins(output: &[String]) -> BTreeMap<String, String> {
+    let mut failing = BTreeMap::new();
+    let mut current_error: Option<&str> = None;
+
+    for line in output {
+        if let Some((_, message)) = line.split_once("[ERROR]") {
+            current_error = Some(message.trim());
+            continue;
+        }
+        if line.contains("[WARNING]") {
+            current_error = None;
+            continue;
+        }
+
+        let Some(plugin) = userplugin_in(line) else {
+            continue;
+        };
+
+        if let Some((_, message)) = line.split_once(": ERROR: ") {
+            failing
+                .entry(plugin)
+                .or_insert_with(|| message.trim().to_string());
+        } else if let Some(message) = current_error {
+            failing.entry(plugin).or_insert_with(|| message.to_string());
+        }
     }
 
-    println!("[Equicord Launcher] pnpm build complete.");
-    Ok(())
+    failing
+}
+
+/// Run the build, retrying without the userplugins that make it fail.
+///
+/// Returns the plugins that were left out, with the error that caused it.
+fn build_without_broken_plugins(
+    repo_dir: &Path,
+    package_manager: PackageManager,
+    options: &BuildOptions,
+    log: &BuildLog,
+) -> Result<Vec<(String, String)>, String> {
+    let userplugins_dir = repo_dir.join("src").join("userplugins");
+    let mut dropped = Vec::new();
+
+    loop {
+        let mut output = Vec::new();
+        let Err(e) = run_build(repo_dir, package_manager, options, log, &mut output) else {
+            return Ok(dropped);
+        };
+
+        if process::cancel_requested() {
+            return Err(e);
+        }
+
+        // Only plugins that are still there, so a misattributed error can't loop forever
+        let failing: Vec<_> = failing_userplugins(&output)
+            .into_iter()
+            .filter(|(plugin, _)| userplugins_dir.join(plugin).exists())
+            .collect();
+
+        if failing.is_empty() {
+            return Err(e);
+        }
+
+        for (plugin, reason) in failing {
+            warn!("userplugin {plugin} failed to compile, building without it: {reason}");
+            log.write_line(&format!("Dropping userplugin {plugin}: {reason}"));
+
+            let path = userplugins_dir.join(&plugin);
+            let removed = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            removed.map_err(|e| format!("Failed to remove userplugin {plugin}: {e}"))?;
+
+            dropped.push((plugin, reason));
+        }
+
+        info!("Retrying the build without the failing userplugins...");
+    }
 }
 
 /// Copy build output from dist/desktop/ to the asset cache directory.
@@ -333,73 +606,205 @@ fn copy_build_output(repo_dir: &Path, cache_dir: &Path) -> Result<(), String> {
         let dest = cache_dir.join(filename);
 
         if src.exists() {
-            std::fs::copy(&src, &dest)
+            // Copy next to the destination and rename over it, so the swap is atomic
+            // and a running Discord never reads a half-written file
+            let tmp = cache_dir.join(format!("{filename}.tmp"));
+            std::fs::copy(&src, &tmp)
                 .map_err(|e| format!("Failed to copy build output '{}': {e}", filename))?;
+            std::fs::rename(&tmp, &dest)
+                .map_err(|e| format!("Failed to replace build output '{}': {e}", filename))?;
         } else {
             // Some files like .LEGAL.txt might not exist in fresh builds
-            eprintln!(
-                "[Equicord Launcher] Warning: build output '{}' not found, skipping.",
-                filename
-            );
+            warn!("build output '{}' not found, skipping.", filename);
         }
     }
 
-    println!("[Equicord Launcher] Build output copied to cache.");
+    info!("Build output copied to cache.");
     Ok(())
 }
 
+/// Options for [`run_custom_build`].
+#[derive(Clone)]
+pub struct BuildOptions {
+    /// Local directories or git URLs, see [`UserpluginSource`].
+    pub userplugin_sources: Vec<String>,
+    /// Fetch the latest Equicord and git userplugin sources before building.
+    /// Watch mode turns this off for rebuilds so that only local changes are picked up.
+    pub update_repos: bool,
+    /// Leave userplugins that fail validation out of the build instead of failing it.
+    pub skip_invalid_plugins: bool,
+    /// Package manager to build with, detected automatically if not set.
+    pub package_manager: Option<PackageManager>,
+    /// Directory of `.patch` files to apply to Equicord before building, see [`patches`](crate::patches).
+    pub patches_dir: Option<PathBuf>,
+    /// Make a development build, which enables dev-only plugins and skips minification.
+    pub dev: bool,
+    /// Extra arguments for Equicord's build script.
+    pub build_args: Vec<String>,
+    /// Extra environment variables for Equicord's build script.
+    pub build_env: Vec<(String, String)>,
+    pub timeouts: StepTimeouts,
+}
+
+/// How long each kind of build step may run before it is killed. `None` means no limit.
+#[derive(Clone, Copy, Debug)]
+pub struct StepTimeouts {
+    /// Each git command, e.g. cloning or fetching Equicord and userplugin sources.
+    pub git: Option<Duration>,
+    pub install: Option<Duration>,
+    pub build: Option<Duration>,
+}
+
+impl BuildOptions {
+    /// Arguments passed to Equicord's build script on top of the package manager's.
+    fn script_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.dev {
+            args.push("--dev".to_string());
+        }
+        args.extend(self.build_args.iter().cloned());
+        args
+    }
+
+    /// Hash the options that change the build output besides the sources.
+    ///
+    /// Returns an empty string without any, matching build states from before build flags.
+    fn flags_hash(&self) -> String {
+        let script_args = self.script_args();
+        if script_args.is_empty() && self.build_env.is_empty() {
+            return String::new();
+        }
+
+        let mut hasher = Sha256::new();
+        for arg in &script_args {
+            hasher.update(b"arg\0");
+            hasher.update(arg.as_bytes());
+            hasher.update([0]);
+        }
+        for (key, value) in &self.build_env {
+            hasher.update(b"env\0");
+            hasher.update(key.as_bytes());
+            hasher.update([0]);
+            hasher.update(value.as_bytes());
+            hasher.update([0]);
+        }
+
+        to_hex(&hasher.finalize())
+    }
+}
+
+/// What a successful custom build left out.
+pub struct BuildOutcome {
+    /// Userplugins that aren't part of the build, with the reason why.
+    pub dropped_plugins: Vec<(String, String)>,
+    pub log_path: Option<PathBuf>,
+}
+
 /// Run the full custom build pipeline.
 ///
-/// Returns `Ok(())` on success, `Err(message)` on failure.
-pub fn run_custom_build(userplugins_dir: &str) -> Result<(), String> {
-    let userplugins_path = PathBuf::from(userplugins_dir);
+/// Subprocess output is streamed to the console and the build log, see [`BuildLog`].
+///
+/// Returns the userplugins left out of an otherwise successful build, or `Err(message)`
+/// on failure.
+pub fn run_custom_build(options: &BuildOptions) -> Result<BuildOutcome, String> {
+    let _cancel_scope = CancelScope::enter();
+    let log = BuildLog::create();
+
+    match build(options, &log) {
+        Ok(dropped_plugins) => {
+            log.write_line("Build succeeded.");
+            Ok(BuildOutcome {
+                dropped_plugins,
+                log_path: log.path().map(Path::to_path_buf),
+            })
+        }
+        Err(e) => {
+            log.write_line(&format!("Build failed: {e}"));
 
-    if !userplugins_path.exists() {
-        return Err(format!(
-            "Userplugins directory does not exist: {}",
-            userplugins_dir
-        ));
+            match log.path() {
+                Some(path) => Err(format!("{e}\n\nBuild log: {}", path.display())),
+                None => Err(e),
+            }
+        }
     }
+}
 
-    if !userplugins_path.is_dir() {
-        return Err(format!(
-            "Userplugins path is not a directory: {}",
-            userplugins_dir
-        ));
+fn build(options: &BuildOptions, log: &BuildLog) -> Result<Vec<(String, String)>, String> {
+    let sources: Vec<_> = options
+        .userplugin_sources
+        .iter()
+        .map(|source| UserpluginSource::parse(source))
+        .collect();
+
+    for source in &sources {
+        source.validate()?;
     }
 
+    let patches = match &options.patches_dir {
+        Some(dir) => find_patches(dir)?,
+        None => Vec::new(),
+    };
+
     let repo_dir = constants::equicord_repo_dir()
         .ok_or_else(|| "Failed to determine Equicord repo directory".to_string())?;
     let cache_dir = constants::asset_cache_dir()
         .ok_or_else(|| "Failed to determine asset cache directory".to_string())?;
 
-    // Step 0: Check prerequisites (before showing progress window)
-    println!("[Equicord Launcher] Checking prerequisites...");
-    check_prerequisites()?;
+    // Step 0: Check prerequisites (before showing progress)
+    info!("Checking prerequisites...");
+    let toolchain = check_prerequisites(options.package_manager)?;
+    let package_manager = toolchain.package_manager;
 
-    // Show progress window
-    #[cfg(windows)]
-    let progress = ProgressWindow::new("Building Equicord with userplugins...", TOTAL_STEPS);
+    // Show progress
+    let progress = reporter::select("Building Equicord with userplugins...", TOTAL_STEPS);
 
-    #[cfg(windows)]
     progress.update(1, "Updating Equicord repository...");
 
     // Step 1: Clone or update repo
-    clone_or_update_repo(&repo_dir)?;
+    if options.update_repos || !repo_dir.join(".git").exists() {
+        clone_or_update_repo(&repo_dir, &options.timeouts, log)?;
+    }
     let git_hash = get_git_hash(&repo_dir)?;
-    println!("[Equicord Launcher] Current commit: {git_hash}");
+    info!("Current commit: {git_hash}");
+
+    // Reapply patches on a clean tree, also undoing the ones from the last build.
+    // A failed build records no state, so its patches only show up as local changes.
+    let previous_state = load_build_state(&cache_dir);
+    let patches_hash = hash_patches(&patches)?;
+    let was_patched = previous_state
+        .as_ref()
+        .is_some_and(|state| !state.patches_hash.is_empty());
+
+    if !patches.is_empty() || was_patched || has_local_changes(&repo_dir) {
+        restore_repo(&repo_dir, options.timeouts.git, log)?;
+        apply_patches(&repo_dir, &patches, options.timeouts.git, log)?;
+    }
+
+    check_engine_requirements(&repo_dir, &toolchain)?;
 
-    #[cfg(windows)]
     progress.update(2, "Syncing userplugins...");
 
-    // Step 2: Sync userplugins
-    sync_userplugins(&userplugins_path, &repo_dir)?;
-    let plugins_hash = hash_directory(&userplugins_path)?;
+    // Step 2: Fetch git sources and sync userplugins
+    let userplugins_paths = sources
+        .iter()
+        .map(|source| source.resolve(options.update_repos, options.timeouts.git, log))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sync_report =
+        sync_userplugins(&userplugins_paths, &repo_dir, options.skip_invalid_plugins)?;
+    let mut dropped_plugins = sync_report.skipped;
+    let plugins_hash = hash_directories(&userplugins_paths, &cache_dir)?;
+
+    let dependencies_hash = hash_dependency_manifests(&repo_dir, package_manager)?;
+    let flags_hash = options.flags_hash();
 
     // Step 3: Check if rebuild is needed
-    let needs_rebuild = match load_build_state(&cache_dir) {
+    let needs_rebuild = match &previous_state {
         Some(state) => {
-            if state.git_commit == git_hash && state.userplugins_hash == plugins_hash {
+            if state.git_commit == git_hash
+                && state.userplugins_hash == plugins_hash
+                && state.patches_hash == patches_hash
+                && state.flags_hash == flags_hash
+            {
                 // Also verify the output files actually exist
                 let all_exist = constants::BUILD_OUTPUT_FILES
                     .iter()
@@ -407,59 +812,347 @@ pub fn run_custom_build(userplugins_dir: &str) -> Result<(), String> {
                     .all(|f| cache_dir.join(f).exists());
 
                 if all_exist {
-                    println!("[Equicord Launcher] Build is up to date, skipping rebuild.");
+                    info!("Build is up to date, skipping rebuild.");
+                    dropped_plugins.extend(state.dropped_plugins.iter().map(|plugin| {
+                        (
+                            plugin.clone(),
+                            "failed to compile when this build was made".to_string(),
+                        )
+                    }));
                     false
                 } else {
-                    println!("[Equicord Launcher] Build output missing, rebuilding...");
+                    info!("Build output missing, rebuilding...");
                     true
                 }
             } else {
-                println!(
-                    "[Equicord Launcher] Changes detected (git: {} -> {}, plugins hash changed: {}), rebuilding...",
+                info!(
+                    "Changes detected (git: {} -> {}, plugins hash changed: {}, patches changed: {}, build flags changed: {}), rebuilding...",
                     state.git_commit,
                     git_hash,
-                    state.userplugins_hash != plugins_hash
+                    state.userplugins_hash != plugins_hash,
+                    state.patches_hash != patches_hash,
+                    state.flags_hash != flags_hash
                 );
                 true
             }
         }
         None => {
-            println!("[Equicord Launcher] No previous build state found, building...");
+            info!("No previous build state found, building...");
             true
         }
     };
 
     if needs_rebuild {
-        #[cfg(windows)]
-        progress.update(3, "Installing dependencies (pnpm install)...");
-
-        // Step 4: pnpm install
-        run_pnpm_install(&repo_dir)?;
+        progress.update(
+            3,
+            &format!("Installing dependencies ({package_manager} install)..."),
+        );
+
+        // Step 4: Install dependencies, unless the lockfile and package.json are unchanged
+        let needs_install = !repo_dir.join("node_modules").is_dir()
+            || previous_state
+                .as_ref()
+                .is_none_or(|state| state.dependencies_hash != dependencies_hash);
+
+        if needs_install {
+            run_install(&repo_dir, &toolchain, options.timeouts.install, log)?;
+        } else {
+            info!("Dependencies unchanged, skipping {package_manager} install.");
+        }
 
-        #[cfg(windows)]
-        progress.update(4, "Building Equicord (pnpm build)...");
+        progress.update(
+            4,
+            &format!("Building Equicord ({package_manager} build)..."),
+        );
 
-        // Step 5: pnpm build
-        run_pnpm_build(&repo_dir)?;
+        // Step 5: Build, leaving out userplugins that fail to compile
+        let failed_plugins =
+            build_without_broken_plugins(&repo_dir, package_manager, options, log)?;
 
-        #[cfg(windows)]
         progress.update(5, "Copying build output...");
 
         // Step 6: Copy output
         copy_build_output(&repo_dir, &cache_dir)?;
 
         // Save build state
-        save_build_state(&cache_dir, &git_hash, &plugins_hash)?;
+        save_build_state(
+            &cache_dir,
+            &BuildState {
+                git_commit: git_hash,
+                userplugins_hash: plugins_hash,
+                dependencies_hash,
+                patches_hash,
+                flags_hash,
+                dropped_plugins: failed_plugins
+                    .iter()
+                    .map(|(plugin, _)| plugin.clone())
+                    .collect(),
+            },
+        )?;
+
+        dropped_plugins.extend(failed_plugins);
     }
 
-    #[cfg(windows)]
-    {
-        progress.update(TOTAL_STEPS, "Build complete!");
-        // Brief pause so user can see "Build complete!"
-        std::thread::sleep(std::time::Duration::from_millis(500));
-        progress.close();
+    progress.update(TOTAL_STEPS, "Build complete!");
+    progress.close();
+
+    info!("Custom build pipeline complete.");
+    Ok(dropped_plugins)
+}
+
+/// Work out what [`run_custom_build`] would do for `--dry-run`, adding its steps to `plan`.
+///
+/// Nothing is fetched, so Equicord's latest commit is looked up with `git ls-remote`
+/// and git userplugin sources are hashed as they are currently checked out.
+pub fn plan_custom_build(options: &BuildOptions, plan: &mut Plan) -> Result<(), String> {
+    let sources: Vec<_> = options
+        .userplugin_sources
+        .iter()
+        .map(|source| UserpluginSource::parse(source))
+        .collect();
+
+    for source in &sources {
+        source.validate()?;
+    }
+
+    let patches = match &options.patches_dir {
+        Some(dir) => find_patches(dir)?,
+        None => Vec::new(),
+    };
+
+    let repo_dir = constants::equicord_repo_dir()
+        .ok_or_else(|| "Failed to determine Equicord repo directory".to_string())?;
+    let cache_dir = constants::asset_cache_path()
+        .ok_or_else(|| "Failed to determine asset cache directory".to_string())?;
+
+    info!("Checking prerequisites...");
+    let toolchain = check_prerequisites(options.package_manager)?;
+    let package_manager = toolchain.package_manager;
+
+    // Step 1: Clone or update repo
+    let current_hash = get_git_hash(&repo_dir).ok();
+    let git_hash = if options.update_repos || current_hash.is_none() {
+        for (cmd, _) in repo_update_commands(&repo_dir) {
+            plan.run(&cmd);
+        }
+
+        match remote_git_hash() {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("{e}");
+                None
+            }
+        }
+    } else {
+        current_hash.clone()
+    };
+
+    let previous_state = load_build_state(&cache_dir);
+    let patches_hash = hash_patches(&patches)?;
+    let was_patched = previous_state
+        .as_ref()
+        .is_some_and(|state| !state.patches_hash.is_empty());
+
+    if !patches.is_empty() || was_patched || has_local_changes(&repo_dir) {
+        for (cmd, _) in restore_commands(&repo_dir) {
+            plan.run(&cmd);
+        }
+        for patch in &patches {
+            plan.run(&apply_command(&repo_dir, patch, &[]));
+        }
+    }
+
+    // Checked against the current checkout, before it would be updated or patched
+    if repo_dir.join("package.json").is_file() {
+        check_engine_requirements(&repo_dir, &toolchain)?;
+    }
+
+    // Step 2: Fetch git sources and sync userplugins
+    let userplugins_paths = sources
+        .iter()
+        .map(|source| source.plan_resolve(options.update_repos, plan))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Sources that aren't checked out yet can only be validated once fetched
+    let fetched_paths: Vec<PathBuf> = userplugins_paths
+        .iter()
+        .filter(|path| path.is_dir())
+        .cloned()
+        .collect();
+    check_userplugins(&fetched_paths, &repo_dir, options.skip_invalid_plugins)?;
+    for path in &userplugins_paths {
+        plan.copy(path, &repo_dir.join("src").join("userplugins"));
+    }
+    let plugins_hash = peek_directories_hash(&userplugins_paths, &cache_dir)?;
+    let flags_hash = options.flags_hash();
+
+    // Step 3: Check if rebuild is needed
+    let needs_rebuild = match (&previous_state, &git_hash) {
+        (None, _) => {
+            info!("No previous build state found, would build.");
+            true
+        }
+        (Some(_), None) => {
+            info!("Couldn't tell whether Equicord changed, assuming it would be rebuilt.");
+            true
+        }
+        (Some(state), Some(git_hash)) => {
+            let changes: Vec<&str> = [
+                (state.git_commit != *git_hash, "Equicord"),
+                (state.userplugins_hash != plugins_hash, "userplugins"),
+                (state.patches_hash != patches_hash, "patches"),
+                (state.flags_hash != flags_hash, "build flags"),
+            ]
+            .into_iter()
+            .filter_map(|(changed, what)| changed.then_some(what))
+            .collect();
+
+            let all_exist = constants::BUILD_OUTPUT_FILES
+                .iter()
+                .filter(|f| !f.contains("LEGAL"))
+                .all(|f| cache_dir.join(f).exists());
+
+            if !changes.is_empty() {
+                info!("Would rebuild, changed: {}.", changes.join(", "));
+                true
+            } else if !all_exist {
+                info!("Build output missing, would rebuild.");
+                true
+            } else {
+                info!("Build is up to date, would skip rebuild.");
+                false
+            }
+        }
+    };
+
+    if !needs_rebuild {
+        return Ok(());
+    }
+
+    // Step 4: Install dependencies, unless the lockfile and package.json are unchanged
+    let dependencies_changed = previous_state.as_ref().is_none_or(|state| {
+        hash_dependency_manifests(&repo_dir, package_manager)
+            .is_ok_and(|hash| hash != state.dependencies_hash)
+    });
+    let needs_install = !repo_dir.join("node_modules").is_dir() || dependencies_changed;
+    // The fetched commit may come with other dependencies
+    let may_need_install = git_hash.is_none() || git_hash != current_hash;
+
+    if needs_install || may_need_install {
+        let condition = (!needs_install).then_some("the dependency manifests changed");
+        plan.run_if(&install_command(&repo_dir, &toolchain), condition);
+        if let Some(lockfile) = leftover_lockfile(&repo_dir, &toolchain) {
+            plan.remove_if(&lockfile, condition);
+        }
     }
 
-    println!("[Equicord Launcher] Custom build pipeline complete.");
+    // Step 5: Build
+    plan.run(&build_command(&repo_dir, package_manager, options));
+
+    // Step 6: Copy output
+    let dist_dir = repo_dir.join("dist").join("desktop");
+    for filename in constants::BUILD_OUTPUT_FILES {
+        plan.copy(&dist_dir.join(filename), &cache_dir.join(filename));
+    }
+    plan.write(&cache_dir.join(constants::CUSTOM_BUILD_STATE_FILE));
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(output: &str) -> Vec<String> {
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn finds_userplugins_in_paths() {
+        assert_eq!(
+            userplugin_in("    src/userplugins/myPlugin/index.ts:3:17:").as_deref(),
+            Some("myPlugin")
+        );
+        assert_eq!(
+            userplugin_in("src\\userplugins\\single.ts:1:0: ERROR: oops").as_deref(),
+            Some("single.ts")
+        );
+        assert_eq!(userplugin_in("    src/plugins/index.ts:3:17:"), None);
+        assert_eq!(userplugin_in("src/userplugins/"), None);
+    }
+
+    #[test]
+    fn attributes_pretty_esbuild_errors() {
+        let output = lines(
+            r#"✘ [ERROR] Could not resolve "missing-package"
+
+    src/userplugins/brokenImport/index.ts:1:17:
+      1 │ import x from "missing-package";
+        ╵               ~~~~~~~~~~~~~~~~~
+
+▲ [WARNING] Comparison using the "===" operator here is always false
+
+    src/userplugins/warnsOnly/index.ts:4:8:
+      4 │     if (-0 === 0) {}
+        ╵        ~~~
+
+✘ [ERROR] Expected ";" but found ")"
+
+    src/userplugins/syntax.tsx:10:4:
+     10 │ foo(;
+        ╵     ^
+
+✘ [ERROR] Another error in the same plugin
+
+    src/userplugins/brokenImport/other.ts:2:0:
+
+3 errors"#,
+        );
+
+        let failing = failing_userplugins(&output);
+        assert_eq!(
+            failing,
+            BTreeMap::from([
+                (
+                    "brokenImport".to_string(),
+                    r#"Could not resolve "missing-package""#.to_string()
+                ),
+                (
+                    "syntax.tsx".to_string(),
+                    r#"Expected ";" but found ")""#.to_string()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn attributes_one_line_esbuild_errors() {
+        let output = lines(
+            "src/userplugins/first/index.ts:3:5: ERROR: No matching export\n\
+            src/plugins/_core/settings.tsx:1:1: ERROR: Not a userplugin\n\
+            src\\userplugins\\second\\index.ts:7:1: ERROR: Unexpected end of file",
+        );
+
+        let failing = failing_userplugins(&output);
+        assert_eq!(
+            failing,
+            BTreeMap::from([
+                ("first".to_string(), "No matching export".to_string()),
+                ("second".to_string(), "Unexpected end of file".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn ignores_errors_outside_userplugins() {
+        let output = lines(
+            r#"✘ [ERROR] Could not resolve "x"
+
+    src/plugins/_core/index.ts:1:17:
+
+Build failed"#,
+        );
+
+        assert!(failing_userplugins(&output).is_empty());
+    }
+}
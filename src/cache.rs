@@ -0,0 +1,389 @@
+//! Inspecting and cleaning the launcher's cached files.
+//!
+//! The cache is split into three parts that can be cleaned separately:
+//! - assets: the Equicord files Discord loads (downloaded or custom built) and OpenAsar
+//! - builds: custom build state and output, cleaning them forces the next `--custom` rebuild
+//! - repo: the Equicord checkout with its `node_modules`, and git userplugin sources
+//!
+//! Everything is recreated on the next launch.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use tinyjson::JsonValue;
+
+use crate::lock::CacheLock;
+use crate::{constants, process};
+
+/// Files in the asset cache that belong to custom builds rather than the assets Discord loads.
+const BUILD_STATE_FILES: &[&str] = &[
+    constants::CUSTOM_BUILD_STATE_FILE,
+    constants::USERPLUGINS_HASH_CACHE_FILE,
+];
+
+/// Which parts of the cache to clean.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CleanTargets {
+    pub assets: bool,
+    pub builds: bool,
+    pub repo: bool,
+}
+
+struct Dirs {
+    assets: PathBuf,
+    repo: PathBuf,
+    sources: PathBuf,
+}
+
+fn dirs() -> Result<Dirs, String> {
+    Ok(Dirs {
+        assets: constants::asset_cache_dir()
+            .ok_or_else(|| "Failed to determine asset cache directory".to_string())?,
+        repo: constants::equicord_repo_dir()
+            .ok_or_else(|| "Failed to determine Equicord repo directory".to_string())?,
+        sources: constants::userplugin_sources_dir()
+            .ok_or_else(|| "Failed to determine userplugin sources directory".to_string())?,
+    })
+}
+
+/// Total size of the files under `path`, not following symlinks.
+/// `exclude` is left out, e.g. the build output inside the repo.
+fn size_of(path: &Path, exclude: Option<&Path>) -> u64 {
+    if exclude == Some(path) {
+        return 0;
+    }
+
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| size_of(&entry.path(), exclude))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Format a size in bytes for humans, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// The asset files, i.e. everything in the asset cache except custom build state.
+fn asset_files(assets_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(assets_dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| !BUILD_STATE_FILES.contains(&name))
+        })
+        .collect();
+
+    files.sort();
+    files
+}
+
+fn build_files(dirs: &Dirs) -> Vec<PathBuf> {
+    BUILD_STATE_FILES
+        .iter()
+        .map(|file| dirs.assets.join(file))
+        .chain(std::iter::once(dirs.repo.join("dist")))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Sizes of the assets, builds and repo parts of the cache.
+fn sizes(dirs: &Dirs) -> [(&'static str, u64); 3] {
+    let dist = dirs.repo.join("dist");
+
+    [
+        (
+            "assets",
+            asset_files(&dirs.assets)
+                .iter()
+                .map(|path| size_of(path, None))
+                .sum(),
+        ),
+        (
+            "builds",
+            build_files(dirs)
+                .iter()
+                .map(|path| size_of(path, None))
+                .sum(),
+        ),
+        (
+            "repo",
+            size_of(&dirs.repo, Some(&dist)) + size_of(&dirs.sources, None),
+        ),
+    ]
+}
+
+/// Print the size of each part of the cache.
+pub fn size() -> Result<(), String> {
+    let dirs = dirs()?;
+    let sizes = sizes(&dirs);
+
+    for (name, size) in &sizes {
+        println!("{name:<8} {:>10}", format_size(*size));
+    }
+    println!(
+        "{:<8} {:>10}",
+        "total",
+        format_size(sizes.iter().map(|(_, size)| size).sum())
+    );
+
+    Ok(())
+}
+
+/// Read a string field from a JSON object file.
+fn read_json_field(path: &Path, field: &str) -> Option<String> {
+    let json: JsonValue = std::fs::read_to_string(path).ok()?.parse().ok()?;
+    let object: &HashMap<_, _> = json.get()?;
+    object.get(field)?.get::<String>().cloned()
+}
+
+/// Print what is in the cache.
+pub fn list() -> Result<(), String> {
+    let dirs = dirs()?;
+
+    println!("Assets ({}):", dirs.assets.display());
+    if let Some(release) =
+        read_json_field(&dirs.assets.join(constants::RELEASE_INFO_FILE), "tag_name")
+    {
+        println!("  Downloaded Equicord release: {release}");
+    }
+    if let Some(commit) = read_json_field(
+        &dirs.assets.join(constants::CUSTOM_BUILD_STATE_FILE),
+        "git_commit",
+    ) {
+        println!("  Custom build of Equicord commit: {commit}");
+    }
+    for path in asset_files(&dirs.assets) {
+        println!(
+            "  {:<32} {:>10}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            format_size(size_of(&path, None))
+        );
+    }
+
+    println!("\nBuilds:");
+    for path in build_files(&dirs) {
+        println!(
+            "  {:<60} {:>10}",
+            path.display(),
+            format_size(size_of(&path, None))
+        );
+    }
+
+    println!("\nRepos:");
+    if dirs.repo.exists() {
+        println!(
+            "  {:<60} {:>10}",
+            dirs.repo.display(),
+            format_size(size_of(&dirs.repo, Some(&dirs.repo.join("dist"))))
+        );
+    }
+    if let Ok(entries) = std::fs::read_dir(&dirs.sources) {
+        let mut sources: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        sources.sort();
+        for source in sources {
+            println!(
+                "  {:<60} {:>10}",
+                source.display(),
+                format_size(size_of(&source, None))
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn remove(path: &Path) -> Result<(), String> {
+    let result = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => return Ok(()),
+    };
+
+    result.map_err(|e| format!("Failed to remove {:?}: {e}", path))
+}
+
+/// Delete the chosen parts of the cache.
+///
+/// Assets are kept while Discord is running, since it loads them from the cache,
+/// unless `force` is set. Fails right away if another launcher instance is using the
+/// cache, unless `force` is set, in which case it waits for it.
+pub fn clean(targets: CleanTargets, force: bool) -> Result<(), String> {
+    let dirs = dirs()?;
+
+    // Don't pull files out from under a launcher that is updating or building.
+    // That can take a while, so only wait for it when forced.
+    let _cache_lock = if force {
+        CacheLock::acquire()?
+    } else {
+        CacheLock::try_acquire().map_err(|e| {
+            format!("{e}\nTry again once it's done, or pass --force to wait for it.")
+        })?
+    };
+
+    let before: u64 = sizes(&dirs).iter().map(|(_, size)| size).sum();
+    let mut errors = Vec::new();
+
+    if targets.assets {
+        let discord_pids = process::running_discord_pids();
+
+        if discord_pids.is_empty() || force {
+            for path in asset_files(&dirs.assets) {
+                if let Err(e) = remove(&path) {
+                    errors.push(e);
+                }
+            }
+        } else {
+            errors.push(format!(
+                "Discord is running (pid {}) and may be using the cached assets, so they were kept.\n\
+                Close Discord and try again, or pass --force.",
+                discord_pids
+                    .iter()
+                    .map(|pid| pid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    if targets.builds {
+        for path in build_files(&dirs) {
+            if let Err(e) = remove(&path) {
+                errors.push(e);
+            }
+        }
+    }
+
+    if targets.repo {
+        for path in [&dirs.repo, &dirs.sources] {
+            if let Err(e) = remove(path) {
+                errors.push(e);
+            }
+        }
+    }
+
+    let after: u64 = sizes(&dirs).iter().map(|(_, size)| size).sum();
+    info!("Freed {}.", format_size(before.saturating_sub(after)));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn check_file(problems: &mut Vec<String>, path: &Path, what: &str) {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() == 0 => {
+            problems.push(format!("{what} is empty: {}", path.display()))
+        }
+        Ok(_) => {}
+        Err(_) => problems.push(format!("{what} is missing: {}", path.display())),
+    }
+}
+
+/// Check that the cached files are complete and readable.
+///
+/// Returns the problems found, an empty list meaning the cache is fine.
+pub fn verify() -> Result<Vec<String>, String> {
+    let dirs = dirs()?;
+    let mut problems = Vec::new();
+
+    // The mod files are either a downloaded release or a custom build
+    let custom_state = dirs.assets.join(constants::CUSTOM_BUILD_STATE_FILE);
+    let release_info = dirs.assets.join(constants::RELEASE_INFO_FILE);
+
+    if custom_state.exists() {
+        if read_json_field(&custom_state, "git_commit").is_none() {
+            problems.push(format!(
+                "Custom build state is corrupt: {}",
+                custom_state.display()
+            ));
+        }
+        // Like the builder, don't require the license files, they aren't always there
+        for file in constants::BUILD_OUTPUT_FILES
+            .iter()
+            .filter(|f| !f.contains("LEGAL"))
+        {
+            check_file(
+                &mut problems,
+                &dirs.assets.join(file),
+                "Custom build output",
+            );
+        }
+    } else if release_info.exists() {
+        if read_json_field(&release_info, "tag_name").is_none() {
+            problems.push(format!(
+                "Release info is corrupt: {}",
+                release_info.display()
+            ));
+        }
+        for file in constants::RELEASE_ASSETS
+            .iter()
+            .filter(|f| !f.contains("LEGAL"))
+        {
+            check_file(&mut problems, &dirs.assets.join(file), "Equicord asset");
+        }
+    } else {
+        problems.push(
+            "No Equicord files are cached yet, they are downloaded on the next launch.".into(),
+        );
+    }
+
+    let open_asar_info = dirs.assets.join(constants::OPEN_ASAR_RELEASE_INFO_FILE);
+    if open_asar_info.exists() {
+        check_file(
+            &mut problems,
+            &dirs.assets.join(constants::OPEN_ASAR_FILENAME),
+            "OpenAsar",
+        );
+    }
+
+    if dirs.repo.exists() {
+        let head = crate::builder::silent_cmd("git")
+            .args(["rev-parse", "--verify", "HEAD"])
+            .current_dir(&dirs.repo)
+            .output();
+
+        if !head.is_ok_and(|output| output.status.success()) {
+            problems.push(format!(
+                "Equicord repo is broken, clean it with `cache clean --repo`: {}",
+                dirs.repo.display()
+            ));
+        }
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,215 @@
+//! The launcher's config file, `config.json` in its config directory.
+//!
+//! Settings are per branch, keyed by the `--branch` names:
+//!
+//! ```json
+//! {
+//!     "branches": {
+//!         "stable": { "discord_path": "/home/me/Apps/Discord" },
+//!         "canary": { "flatpak": "com.discordapp.DiscordCanary" }
+//!     }
+//! }
+//! ```
+//!
+//! Command line options take precedence over the config file.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::warn;
+use tinyjson::JsonValue;
+
+use crate::constants;
+use crate::discord::{DiscordBranch, DiscordOverride};
+
+#[derive(Default)]
+pub struct BranchConfig {
+    /// Discord executable or install directory to use instead of looking for one.
+    pub discord_path: Option<PathBuf>,
+    /// Flatpak app ID to launch instead of looking for Discord.
+    pub flatpak: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Config {
+    branches: HashMap<String, BranchConfig>,
+}
+
+/// The string at `key` in `object`, failing if it's something else.
+fn optional_string(
+    object: &HashMap<String, JsonValue>,
+    key: &str,
+    context: &str,
+) -> Result<Option<String>, String> {
+    match object.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(format!("{context}.{key} must be a string")),
+    }
+}
+
+fn parse(data: &str) -> Result<Config, String> {
+    let json: JsonValue = data.parse().map_err(|e| format!("{e:?}"))?;
+    let object: &HashMap<_, _> = json.get().ok_or("expected an object")?;
+
+    let mut config = Config::default();
+
+    let Some(branches) = object.get("branches") else {
+        return Ok(config);
+    };
+    let branches: &HashMap<_, _> = branches.get().ok_or("branches must be an object")?;
+
+    for (name, branch) in branches {
+        if !DiscordBranch::ALL.iter().any(|known| known.id() == name) {
+            warn!("Ignoring unknown branch '{name}' in the config file.");
+            continue;
+        }
+
+        let context = format!("branches.{name}");
+        let branch: &HashMap<_, _> = branch
+            .get()
+            .ok_or_else(|| format!("{context} must be an object"))?;
+
+        config.branches.insert(
+            name.clone(),
+            BranchConfig {
+                discord_path: optional_string(branch, "discord_path", &context)?.map(PathBuf::from),
+                flatpak: optional_string(branch, "flatpak", &context)?,
+            },
+        );
+    }
+
+    Ok(config)
+}
+
+static CONFIG: OnceLock<Result<Config, String>> = OnceLock::new();
+
+/// The config file, loaded on first use. A missing file is the same as an empty one.
+///
+/// Must not be called before the data directory is set, since the file may live in it.
+pub fn load() -> Result<&'static Config, String> {
+    CONFIG.get_or_init(read).as_ref().map_err(String::clone)
+}
+
+fn read() -> Result<Config, String> {
+    let Some(path) = constants::config_file() else {
+        return Ok(Config::default());
+    };
+
+    let data = match std::fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+    };
+
+    parse(&data).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+}
+
+impl Config {
+    pub fn branch(&self, branch: DiscordBranch) -> Option<&BranchConfig> {
+        self.branches.get(branch.id())
+    }
+
+    /// The install configured for `branch`, if any.
+    pub fn discord_override(
+        &self,
+        branch: DiscordBranch,
+    ) -> Result<Option<DiscordOverride>, String> {
+        let Some(config) = self.branch(branch) else {
+            return Ok(None);
+        };
+
+        match (&config.discord_path, &config.flatpak) {
+            (Some(_), Some(_)) => Err(format!(
+                "The config file sets both discord_path and flatpak for {}, remove one.",
+                branch.id()
+            )),
+            (Some(path), None) => Ok(Some(DiscordOverride::Path(path.clone()))),
+            (None, Some(app_id)) => Ok(Some(DiscordOverride::Flatpak(app_id.clone()))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn parses_branch_overrides() {
+        let config = parse(
+            r#"{
+                "branches": {
+                    "stable": { "discord_path": "/opt/discord" },
+                    "canary": { "flatpak": "com.discordapp.DiscordCanary", "discord_path": null }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            config.discord_override(DiscordBranch::Stable),
+            Ok(Some(DiscordOverride::Path(path))) if path == Path::new("/opt/discord")
+        ));
+        assert!(matches!(
+            config.discord_override(DiscordBranch::Canary),
+            Ok(Some(DiscordOverride::Flatpak(id))) if id == "com.discordapp.DiscordCanary"
+        ));
+        assert!(matches!(
+            config.discord_override(DiscordBranch::PTB),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn empty_configs() {
+        for data in [
+            "{}",
+            r#"{ "branches": {} }"#,
+            r#"{ "branches": { "ptb": {} } }"#,
+        ] {
+            let config = parse(data).unwrap();
+            assert!(matches!(
+                config.discord_override(DiscordBranch::PTB),
+                Ok(None)
+            ));
+        }
+    }
+
+    #[test]
+    fn skips_unknown_branches() {
+        let config = parse(r#"{ "branches": { "nightly": { "flatpak": 1 } } }"#).unwrap();
+        assert!(config.branches.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        let error = |data: &str| parse(data).err().unwrap();
+
+        assert!(parse("not json").is_err());
+        assert_eq!(error("[]"), "expected an object");
+        assert_eq!(error(r#"{ "branches": [] }"#), "branches must be an object");
+        assert_eq!(
+            error(r#"{ "branches": { "stable": "/opt/discord" } }"#),
+            "branches.stable must be an object"
+        );
+        assert_eq!(
+            error(r#"{ "branches": { "canary": { "flatpak": true } } }"#),
+            "branches.canary.flatpak must be a string"
+        );
+    }
+
+    #[test]
+    fn rejects_both_path_and_flatpak() {
+        let config = parse(
+            r#"{ "branches": { "stable": { "discord_path": "/opt/discord", "flatpak": "com.discordapp.Discord" } } }"#,
+        )
+        .unwrap();
+
+        let error = config.discord_override(DiscordBranch::Stable).unwrap_err();
+        assert!(error.contains("both discord_path and flatpak for stable"));
+    }
+}
@@ -28,8 +28,50 @@ pub static RELEASE_ASSETS: &[&str] = &[
     "renderer.css.map",
 ];
 
+// Default build step timeouts, in seconds
+pub static DEFAULT_GIT_TIMEOUT_SECS: u64 = 300;
+pub static DEFAULT_INSTALL_TIMEOUT_SECS: u64 = 900;
+pub static DEFAULT_BUILD_TIMEOUT_SECS: u64 = 600;
+
+/// Minimum tool versions for custom builds. Equicord's package.json may ask for newer ones.
+pub static MIN_GIT_VERSION: &str = "2.20.0";
+pub static MIN_NODE_VERSION: &str = "18.0.0";
+pub static MIN_PNPM_VERSION: &str = "9.0.0";
+
 pub static EQUICORD_REPO_URL: &str = "https://github.com/Equicord/Equicord.git";
 pub static CUSTOM_BUILD_STATE_FILE: &str = "custom_build_state.json";
+pub static BUILD_LOG_FILE: &str = "build.log";
+pub static LAUNCHER_LOG_FILE: &str = "launcher.log";
+pub static LOCK_FILE: &str = "launcher.lock";
+pub static CONFIG_FILE: &str = "config.json";
+pub static USERPLUGINS_HASH_CACHE_FILE: &str = "userplugins_hash_cache.json";
+pub static USERPLUGIN_IGNORE_FILE: &str = ".equicordignore";
+
+/// `.gitignore`-style patterns that are never synced into the Equicord repo or hashed,
+/// in addition to those in a userplugins directory's `.equicordignore`.
+pub static DEFAULT_USERPLUGIN_IGNORES: &[&str] = &[
+    ".equicordignore",
+    // Version control and dependencies
+    ".git",
+    "node_modules/",
+    // Build output
+    "dist/",
+    "*.log",
+    // Editor and OS junk
+    ".idea/",
+    ".vscode/",
+    "*.swp",
+    "*.swo",
+    "*~",
+    ".#*",
+    "#*#",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// Files in the Equicord repo that decide what `pnpm install` puts in `node_modules`.
+pub static DEPENDENCY_MANIFESTS: &[&str] = &["package.json", "pnpm-lock.yaml"];
 
 /// Files produced by `pnpm build` in `dist/desktop/` that we copy to the asset cache.
 pub static BUILD_OUTPUT_FILES: &[&str] = &[
@@ -71,10 +113,154 @@ pub fn get_library_path() -> String {
     LIBRARY.to_string()
 }
 
-pub fn asset_cache_dir() -> Option<std::path::PathBuf> {
-    let local_appdata = dirs::data_local_dir()?;
+/// Directory name used under the platform's data, cache and state directories.
+pub static APP_DIR_NAME: &str = "EquicordLauncher";
+
+/// Environment variable overriding where the launcher keeps its files, like `--data-dir`.
+pub static DATA_DIR_ENV: &str = "EQUICORD_LAUNCHER_HOME";
+
+/// A file with this name next to the executable enables portable mode,
+/// which keeps all files in a `data` directory next to the executable.
+pub static PORTABLE_MARKER_FILE: &str = "portable";
+pub static PORTABLE_DATA_DIR: &str = "data";
+
+static DATA_DIR_OVERRIDE: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
 
-    let dir = local_appdata.join("EquicordLauncher").join("cache");
+/// Keep all files in `dir`, as passed with `--data-dir`. Must be called before any
+/// of the directory functions below, later calls are ignored.
+pub fn set_data_dir(dir: &std::path::Path) {
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let _ = DATA_DIR_OVERRIDE.set(dir);
+}
+
+/// The single directory holding all files, if one was configured through `--data-dir`,
+/// the environment or portable mode.
+pub fn data_dir_override() -> Option<std::path::PathBuf> {
+    if let Some(dir) = DATA_DIR_OVERRIDE.get() {
+        return Some(dir.clone());
+    }
+
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        let dir = std::path::PathBuf::from(dir);
+        return Some(std::path::absolute(&dir).unwrap_or(dir));
+    }
+
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    if exe_dir.join(PORTABLE_MARKER_FILE).is_file() {
+        return Some(exe_dir.join(PORTABLE_DATA_DIR));
+    }
+
+    None
+}
+
+/// Where downloaded and built files go. These can always be recreated.
+///
+/// On Linux this is `$XDG_CACHE_HOME/EquicordLauncher` unless overridden.
+pub fn cache_root() -> Option<std::path::PathBuf> {
+    if let Some(dir) = data_dir_override() {
+        return Some(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    let base = dirs::cache_dir()?;
+
+    #[cfg(not(target_os = "linux"))]
+    let base = dirs::data_local_dir()?;
+
+    Some(base.join(APP_DIR_NAME))
+}
+
+/// Where logs and the lock file go.
+///
+/// On Linux this is `$XDG_STATE_HOME/EquicordLauncher` unless overridden.
+pub fn state_root() -> Option<std::path::PathBuf> {
+    if let Some(dir) = data_dir_override() {
+        return Some(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    let base = dirs::state_dir()?;
+
+    #[cfg(not(target_os = "linux"))]
+    let base = dirs::data_local_dir()?;
+
+    Some(base.join(APP_DIR_NAME))
+}
+
+/// Where the config file goes.
+///
+/// On Linux this is `$XDG_CONFIG_HOME/EquicordLauncher` unless overridden.
+pub fn config_root() -> Option<std::path::PathBuf> {
+    if let Some(dir) = data_dir_override() {
+        return Some(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    let base = dirs::config_dir()?;
+
+    #[cfg(not(target_os = "linux"))]
+    let base = dirs::data_local_dir()?;
+
+    Some(base.join(APP_DIR_NAME))
+}
+
+pub fn config_file() -> Option<std::path::PathBuf> {
+    Some(config_root()?.join(CONFIG_FILE))
+}
+
+/// Move files from `~/.local/share/EquicordLauncher`, where older versions kept everything
+/// on Linux, to their XDG cache and state directories.
+///
+/// Anything that can't be moved, e.g. because it's on another filesystem, is left behind
+/// and simply recreated.
+#[cfg(target_os = "linux")]
+pub fn migrate_legacy_data_dir() {
+    if data_dir_override().is_some() {
+        return;
+    }
+
+    let Some(legacy) = dirs::data_local_dir().map(|dir| dir.join(APP_DIR_NAME)) else {
+        return;
+    };
+    if !legacy.is_dir() {
+        return;
+    }
+
+    let (Some(cache), Some(state)) = (cache_root(), state_root()) else {
+        return;
+    };
+
+    // Logs first, so the launcher log is started in its new location
+    for (name, root) in [
+        ("logs", &state),
+        ("cache", &cache),
+        ("equicord-repo", &cache),
+        ("userplugin-sources", &cache),
+    ] {
+        let from = legacy.join(name);
+        let to = root.join(name);
+
+        if !from.exists() || to.exists() {
+            continue;
+        }
+
+        if std::fs::create_dir_all(root).is_ok() && std::fs::rename(&from, &to).is_ok() {
+            log::info!("Moved {:?} to {:?}.", from, to);
+        }
+    }
+
+    let _ = std::fs::remove_file(legacy.join(LOCK_FILE));
+    // Only succeeds if nothing was left behind
+    let _ = std::fs::remove_dir(&legacy);
+}
+
+/// The asset cache directory, without creating it like [`asset_cache_dir`] does.
+pub fn asset_cache_path() -> Option<std::path::PathBuf> {
+    Some(cache_root()?.join("cache"))
+}
+
+pub fn asset_cache_dir() -> Option<std::path::PathBuf> {
+    let dir = asset_cache_path()?;
 
     if !dir.exists() {
         std::fs::create_dir_all(&dir).ok()?;
@@ -84,10 +270,29 @@ pub fn asset_cache_dir() -> Option<std::path::PathBuf> {
 }
 
 pub fn equicord_repo_dir() -> Option<std::path::PathBuf> {
-    let local_appdata = dirs::data_local_dir()?;
-
-    let dir = local_appdata.join("EquicordLauncher").join("equicord-repo");
+    let dir = cache_root()?.join("equicord-repo");
 
     // Don't create it here — the builder will clone into it
     Some(dir)
 }
+
+pub fn userplugin_sources_dir() -> Option<std::path::PathBuf> {
+    let dir = cache_root()?.join("userplugin-sources");
+
+    // Don't create it here — each git source is cloned into its own subdirectory
+    Some(dir)
+}
+
+pub fn logs_dir() -> Option<std::path::PathBuf> {
+    Some(state_root()?.join("logs"))
+}
+
+pub fn lock_file_path() -> Option<std::path::PathBuf> {
+    let dir = state_root()?;
+
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir).ok()?;
+    }
+
+    Some(dir.join(LOCK_FILE))
+}
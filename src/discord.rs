@@ -1,8 +1,12 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use electron_hook::FlatpakID;
+use log::debug;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+use crate::config;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DiscordBranch {
     Stable,
     Canary,
@@ -10,11 +14,216 @@ pub enum DiscordBranch {
     Development,
 }
 
+impl DiscordBranch {
+    pub const ALL: [DiscordBranch; 4] = [
+        DiscordBranch::Stable,
+        DiscordBranch::PTB,
+        DiscordBranch::Canary,
+        DiscordBranch::Development,
+    ];
+
+    /// The branch a launcher binary or symlink named `program` is for,
+    /// e.g. `equicord-canary`. `None` if the name doesn't mention one.
+    pub fn from_program_name(program: &str) -> Option<DiscordBranch> {
+        let name = std::path::Path::new(program)
+            .file_stem()?
+            .to_string_lossy()
+            .to_lowercase();
+
+        name.rsplit(['-', '_', '.', ' '])
+            .find_map(|word| match word {
+                "stable" => Some(DiscordBranch::Stable),
+                "ptb" => Some(DiscordBranch::PTB),
+                "canary" => Some(DiscordBranch::Canary),
+                "development" | "dev" => Some(DiscordBranch::Development),
+                _ => None,
+            })
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            DiscordBranch::Stable => "Discord Stable",
+            DiscordBranch::PTB => "Discord PTB",
+            DiscordBranch::Canary => "Discord Canary",
+            DiscordBranch::Development => "Discord Development",
+        }
+    }
+
+    /// Lowercase name used in window classes and machine-readable output.
+    pub fn id(self) -> &'static str {
+        match self {
+            DiscordBranch::Stable => "stable",
+            DiscordBranch::PTB => "ptb",
+            DiscordBranch::Canary => "canary",
+            DiscordBranch::Development => "development",
+        }
+    }
+
+    /// The name of the branch's executable, without extension.
+    fn executable_name(self) -> &'static str {
+        match self {
+            DiscordBranch::Stable => "Discord",
+            DiscordBranch::PTB => "DiscordPTB",
+            DiscordBranch::Canary => "DiscordCanary",
+            DiscordBranch::Development => "DiscordDevelopment",
+        }
+    }
+
+    /// Identifies the launcher's hook for this branch.
+    pub fn instance_id(self) -> &'static str {
+        match self {
+            DiscordBranch::Stable => "EquicordStable",
+            DiscordBranch::PTB => "EquicordPTB",
+            DiscordBranch::Canary => "EquicordCanary",
+            DiscordBranch::Development => "EquicordDevelopment",
+        }
+    }
+}
+
 pub enum DiscordPath {
     Filesystem(PathBuf),
     FlatpakId(FlatpakID),
 }
 
+/// An install to use instead of looking for one, from `--discord-path`, `--flatpak`
+/// or the branch's entry in the config file.
+#[derive(Clone, Debug)]
+pub enum DiscordOverride {
+    /// A Discord executable or the directory containing it.
+    Path(PathBuf),
+    /// A Flatpak app ID.
+    Flatpak(String),
+}
+
+static COMMAND_LINE_OVERRIDE: OnceLock<(DiscordBranch, DiscordOverride)> = OnceLock::new();
+
+/// Use `discord_override` for `branch`, as passed on the command line.
+/// Takes precedence over the config file. Later calls are ignored.
+pub fn set_override(branch: DiscordBranch, discord_override: DiscordOverride) {
+    let _ = COMMAND_LINE_OVERRIDE.set((branch, discord_override));
+}
+
+/// Find `branch`, using the install from the command line or the config file if one is set.
+///
+/// Fails if that install isn't usable, rather than falling back to another one.
+pub fn find_discord(branch: DiscordBranch) -> Result<Option<DiscordPath>, String> {
+    let discord_override = match COMMAND_LINE_OVERRIDE.get() {
+        Some((override_branch, discord_override)) if *override_branch == branch => {
+            Some(discord_override.clone())
+        }
+        _ => config::load()?.discord_override(branch)?,
+    };
+
+    match discord_override {
+        Some(DiscordOverride::Path(path)) => {
+            debug!("Using {} at {:?}", branch.display_name(), path);
+            discord_executable(branch, &path).map(|path| Some(DiscordPath::Filesystem(path)))
+        }
+        Some(DiscordOverride::Flatpak(app_id)) => {
+            debug!("Using {} as Flatpak {app_id}", branch.display_name());
+            flatpak_install(&app_id).map(|id| Some(DiscordPath::FlatpakId(id)))
+        }
+        None => Ok(get_discord(branch)),
+    }
+}
+
+/// The Discord executable at `path`, or in the install directory `path`.
+fn discord_executable(branch: DiscordBranch, path: &Path) -> Result<PathBuf, String> {
+    if !path.is_dir() {
+        return check_discord_executable(path);
+    }
+
+    #[cfg(windows)]
+    if path.join("Update.exe").exists() {
+        return crate::windows::get_latest_executable(path)
+            .and_then(|executable| check_discord_executable(&executable));
+    }
+
+    // Prefer the branch's own executable, but any Discord build will do
+    let names = std::iter::once(branch)
+        .chain(DiscordBranch::ALL)
+        .map(DiscordBranch::executable_name);
+
+    names
+        .flat_map(|name| [name.to_string(), name.to_lowercase()])
+        .map(|name| path.join(format!("{name}{}", std::env::consts::EXE_SUFFIX)))
+        .find(|executable| executable.is_file())
+        .ok_or_else(|| format!("No Discord executable found in {}", path.display()))
+        .and_then(|executable| check_discord_executable(&executable))
+}
+
+/// Check that `executable` is an Electron build of Discord the launcher can hook,
+/// rather than e.g. a wrapper script.
+fn check_discord_executable(executable: &Path) -> Result<PathBuf, String> {
+    // Packages often link the executable into a bin directory
+    #[cfg(unix)]
+    let resolved = std::fs::canonicalize(executable);
+    #[cfg(not(unix))]
+    let resolved = std::path::absolute(executable);
+
+    let executable =
+        resolved.map_err(|e| format!("Can't find Discord at {}: {e}", executable.display()))?;
+    if !executable.is_file() {
+        return Err(format!("{} is not a file.", executable.display()));
+    }
+
+    let resources_dir = executable
+        .parent()
+        .map(|dir| dir.join("resources"))
+        .unwrap_or_default();
+
+    // OpenAsar installs keep the original as `_app.asar`
+    if !["app.asar", "_app.asar"]
+        .iter()
+        .any(|asar| resources_dir.join(asar).is_file())
+    {
+        return Err(format!(
+            "{} is not an Electron app, there is no resources/app.asar next to it.",
+            executable.display()
+        ));
+    }
+
+    if !resources_dir.join("build_info.json").is_file() {
+        return Err(format!(
+            "{} is not Discord, there is no resources/build_info.json next to it.",
+            executable.display()
+        ));
+    }
+
+    Ok(executable)
+}
+
+/// The installation of the Flatpak `app_id`.
+#[cfg(target_os = "linux")]
+fn flatpak_install(app_id: &str) -> Result<FlatpakID, String> {
+    find_flatpak(app_id).ok_or_else(|| format!("Flatpak {app_id} is not installed."))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn flatpak_install(_app_id: &str) -> Result<FlatpakID, String> {
+    Err("Flatpak is only supported on Linux.".to_string())
+}
+
+/// Look for a user install of the Flatpak `app_id`, then a system one.
+#[cfg(target_os = "linux")]
+fn find_flatpak(app_id: &str) -> Option<FlatpakID> {
+    let flatpak_dir = dirs::data_local_dir()?.join(format!("flatpak/app/{app_id}/current/active/"));
+    debug!("Looking for a user Flatpak at {:?}", flatpak_dir);
+
+    if flatpak_dir.is_dir() {
+        return Some(FlatpakID::User(app_id.to_string()));
+    }
+
+    let flatpak_dir = PathBuf::from(format!("/var/lib/flatpak/app/{app_id}/current/active/"));
+    debug!("Looking for a system Flatpak at {:?}", flatpak_dir);
+
+    if flatpak_dir.is_dir() {
+        return Some(FlatpakID::System(app_id.to_string()));
+    }
+
+    None
+}
+
 #[cfg(windows)]
 pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
     use crate::windows::get_latest_executable;
@@ -28,6 +237,7 @@ pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
     };
 
     let dir = local_appdata.join(name);
+    debug!("Looking for {} in {:?}", branch.display_name(), dir);
 
     if !dir.join("Update.exe").exists() {
         return None;
@@ -62,6 +272,7 @@ pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
 
     // On linux, the executable is at /home/user/.local/share/DiscordCanary/DiscordCanary
     let executable = local_share.join(name).join(name);
+    debug!("Looking for {} at {:?}", branch.display_name(), executable);
 
     if executable.is_file() {
         return Some(DiscordPath::Filesystem(executable));
@@ -69,11 +280,13 @@ pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
 
     // If that doesn't work, try $HOME/.dvm/branches
     let executable = dirs::home_dir()?.join(format!(".dvm/branches/{lower_name}/{name}/{name}"));
+    debug!("Looking for {} at {:?}", branch.display_name(), executable);
     if executable.is_file() {
         return Some(DiscordPath::Filesystem(executable));
     }
 
     let executable = PathBuf::from(format!("/usr/bin/discord-{lower_name}"));
+    debug!("Looking for {} at {:?}", branch.display_name(), executable);
     if executable.is_file() {
         return Some(DiscordPath::Filesystem(executable));
     }
@@ -85,6 +298,7 @@ pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
         format!("discord-{lower_name}")
     };
 
+    debug!("Looking for {} in PATH as {command}", branch.display_name());
     let command_output = Command::new("sh")
         .arg("-c")
         .arg(format!("command -v {}", command))
@@ -104,28 +318,49 @@ pub fn get_discord(branch: DiscordBranch) -> Option<DiscordPath> {
         DiscordBranch::Development => "com.discordapp.DiscordDevelopment",
     };
 
-    let flatpak_dir = local_share.join(format!("flatpak/app/{flatpak_name}/current/active/"));
+    debug!(
+        "Looking for {} as Flatpak {flatpak_name}",
+        branch.display_name()
+    );
+    find_flatpak(flatpak_name).map(DiscordPath::FlatpakId)
+}
 
-    if flatpak_dir.is_dir() {
-        return Some(DiscordPath::FlatpakId(FlatpakID::User(
-            flatpak_name.to_string(),
-        )));
-    }
+#[cfg(target_os = "macos")]
+pub fn get_discord(name: &str) -> Option<PathBuf> {
+    todo!();
+}
 
-    let flatpak_dir = PathBuf::from(format!(
-        "/var/lib/flatpak/app/{flatpak_name}/current/active/"
-    ));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if flatpak_dir.is_dir() {
-        return Some(DiscordPath::FlatpakId(FlatpakID::System(
-            flatpak_name.to_string(),
-        )));
+    #[test]
+    fn branch_from_program_name() {
+        let branch = DiscordBranch::from_program_name;
+
+        assert_eq!(branch("equicord-canary"), Some(DiscordBranch::Canary));
+        assert_eq!(
+            branch("/usr/local/bin/equicord-ptb"),
+            Some(DiscordBranch::PTB)
+        );
+        assert_eq!(branch("equicord-stable.exe"), Some(DiscordBranch::Stable));
+        assert_eq!(branch("Equicord Canary"), Some(DiscordBranch::Canary));
+        assert_eq!(branch("equicord_dev"), Some(DiscordBranch::Development));
+        assert_eq!(
+            branch("equicord-development"),
+            Some(DiscordBranch::Development)
+        );
+        assert_eq!(branch("canary-equicord"), Some(DiscordBranch::Canary));
     }
 
-    None
-}
+    #[test]
+    fn no_branch_in_program_name() {
+        let branch = DiscordBranch::from_program_name;
 
-#[cfg(target_os = "macos")]
-pub fn get_discord(name: &str) -> Option<PathBuf> {
-    todo!();
+        assert_eq!(branch("equicord"), None);
+        assert_eq!(branch("equicord.exe"), None);
+        assert_eq!(branch("equicord-canaryish"), None);
+        assert_eq!(branch("/opt/canary/equicord"), None);
+        assert_eq!(branch(""), None);
+    }
 }
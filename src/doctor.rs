@@ -0,0 +1,581 @@
+//! Diagnostics for when Equicord doesn't load.
+//!
+//! `doctor` runs the checks we'd otherwise walk someone through by hand and prints
+//! each result with a hint on how to fix it.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tinyjson::JsonValue;
+
+use crate::builder::silent_cmd;
+use crate::discord::{self, DiscordBranch, DiscordPath};
+use crate::package_manager::PackageManager;
+use crate::userplugins::files_equal;
+use crate::version::{Version, VersionReq};
+use crate::{cache, constants, updater};
+
+static GITHUB_RATE_LIMIT_URL: &str = "https://api.github.com/rate_limit";
+
+/// How long to wait for GitHub before reporting it unreachable.
+const GITHUB_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// The result of a single check.
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    /// How to fix a warning or failure.
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &str, detail: impl Into<String>, hint: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &str, detail: impl Into<String>, hint: impl Into<String>) -> Check {
+        Check {
+            name: name.to_string(),
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Run every check for a launcher of `branch`.
+pub fn run_checks(branch: DiscordBranch) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for other in DiscordBranch::ALL {
+        let name = other.display_name();
+        let discord_path = match discord::find_discord(other) {
+            Ok(discord_path) => discord_path,
+            Err(e) => {
+                checks.push(Check::fail(
+                    name,
+                    e,
+                    "Fix --discord-path or --flatpak, or the branch's entry in config.json.",
+                ));
+                continue;
+            }
+        };
+        let Some(discord_path) = discord_path else {
+            checks.push(if other == branch {
+                Check::fail(
+                    name,
+                    "not found",
+                    format!(
+                        "Install {name}, or reinstall it if it is installed somewhere unusual."
+                    ),
+                )
+            } else {
+                Check::pass(name, "not installed")
+            });
+            continue;
+        };
+
+        checks.push(Check::pass(name, describe(&discord_path)));
+
+        match &discord_path {
+            DiscordPath::Filesystem(executable) => checks.push(check_open_asar(name, executable)),
+            #[cfg(target_os = "linux")]
+            DiscordPath::FlatpakId(id) => checks.push(check_flatpak(name, id)),
+            #[cfg(not(target_os = "linux"))]
+            DiscordPath::FlatpakId(_) => {}
+        }
+    }
+
+    checks.push(check_library());
+    checks.push(check_cache());
+    checks.push(check_github());
+    checks.extend(check_tools());
+
+    checks
+}
+
+fn describe(discord_path: &DiscordPath) -> String {
+    match discord_path {
+        DiscordPath::Filesystem(executable) => executable.display().to_string(),
+        DiscordPath::FlatpakId(electron_hook::FlatpakID::User(id)) => {
+            format!("Flatpak {id} (user)")
+        }
+        DiscordPath::FlatpakId(electron_hook::FlatpakID::System(id)) => {
+            format!("Flatpak {id} (system)")
+        }
+    }
+}
+
+/// Format the results, one line per check plus hints, followed by a summary.
+pub fn format_checks(checks: &[Check]) -> String {
+    let mut report = String::new();
+
+    for check in checks {
+        let status = match check.status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        report.push_str(&format!("[{status}] {}: {}\n", check.name, check.detail));
+
+        if let Some(hint) = &check.hint {
+            for line in hint.lines() {
+                report.push_str(&format!("       {line}\n"));
+            }
+        }
+    }
+
+    let count = |status| checks.iter().filter(|check| check.status == status).count();
+    report.push_str(&format!(
+        "\n{} passed, {} warning(s), {} failed.\n",
+        count(Status::Pass),
+        count(Status::Warn),
+        count(Status::Fail)
+    ));
+
+    report
+}
+
+/// The ELF or PE machine type the launcher was built for.
+fn expected_machine() -> Option<u16> {
+    #[cfg(all(unix, target_arch = "x86_64"))]
+    return Some(62);
+    #[cfg(all(unix, target_arch = "aarch64"))]
+    return Some(183);
+    #[cfg(all(windows, target_arch = "x86_64"))]
+    return Some(0x8664);
+    #[cfg(all(windows, target_arch = "aarch64"))]
+    return Some(0xaa64);
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Read the machine type from an ELF (Unix) or PE (Windows) header.
+fn library_machine(bytes: &[u8]) -> Option<u16> {
+    let read_u16 = |offset: usize| {
+        bytes
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+
+    if cfg!(windows) {
+        if !bytes.starts_with(b"MZ") {
+            return None;
+        }
+        let pe_offset = bytes.get(0x3c..0x40)?;
+        let pe_offset = u32::from_le_bytes(pe_offset.try_into().ok()?) as usize;
+        if bytes.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+            return None;
+        }
+        read_u16(pe_offset + 4)
+    } else {
+        if !bytes.starts_with(b"\x7fELF") {
+            return None;
+        }
+        read_u16(18)
+    }
+}
+
+fn check_library() -> Check {
+    let name = "Launcher library";
+    let library_path = constants::get_library_path();
+
+    // get_library_path falls back to the bare file name if it can't find the library
+    if library_path == constants::LIBRARY {
+        return Check::fail(
+            name,
+            format!("{} not found", constants::LIBRARY),
+            format!(
+                "Keep {} next to the launcher executable, it is what loads Equicord into Discord.",
+                constants::LIBRARY
+            ),
+        );
+    }
+
+    let bytes = match std::fs::read(&library_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return Check::fail(
+                name,
+                format!("{library_path} can't be read: {e}"),
+                "Check the file's permissions, or reinstall the launcher.",
+            )
+        }
+    };
+
+    let reinstall_hint = "Reinstall the launcher, using the download for your platform.";
+    match library_machine(&bytes) {
+        None => {
+            return Check::fail(
+                name,
+                format!("{library_path} is not a shared library"),
+                reinstall_hint,
+            )
+        }
+        Some(machine) if expected_machine().is_some_and(|expected| expected != machine) => {
+            return Check::fail(
+                name,
+                format!("{library_path} was built for a different CPU architecture"),
+                reinstall_hint,
+            )
+        }
+        Some(_) => {}
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(output) = std::process::Command::new("ldd")
+        .arg(&library_path)
+        .output()
+    {
+        let missing: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.contains("not found"))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+
+        if !missing.is_empty() {
+            return Check::fail(
+                name,
+                format!(
+                    "{library_path} needs missing libraries: {}",
+                    missing.join(", ")
+                ),
+                "Install the missing libraries with your package manager.",
+            );
+        }
+    }
+
+    Check::pass(name, library_path)
+}
+
+fn check_cache() -> Check {
+    let name = "Cache";
+
+    match cache::verify() {
+        Ok(problems) if problems.is_empty() => Check::pass(name, "complete"),
+        Ok(problems) => Check::fail(
+            name,
+            problems.join("; "),
+            "Launch Discord through the launcher to download missing files.\n\
+            If that doesn't help, run `cache clean --assets` and launch again.",
+        ),
+        Err(e) => Check::fail(
+            name,
+            e,
+            "Check that the launcher's data directory is writable.",
+        ),
+    }
+}
+
+/// Whether the Discord install at `executable` has OpenAsar in place,
+/// mirroring how the launcher patches it in.
+fn check_open_asar(discord_name: &str, executable: &Path) -> Check {
+    let name = format!("{discord_name} OpenAsar");
+    let name = name.as_str();
+
+    let Some(open_asar) = constants::asset_cache_dir()
+        .map(|dir| dir.join(constants::OPEN_ASAR_FILENAME))
+        .filter(|path| path.exists())
+    else {
+        return Check::warn(
+            name,
+            "not downloaded yet",
+            "It is downloaded on the next launch, see the GitHub check if that keeps failing.",
+        );
+    };
+
+    let resources_dir = executable.parent().unwrap_or(executable).join("resources");
+    let app_asar = resources_dir.join("app.asar");
+    let backup_asar = resources_dir.join("_app.asar");
+
+    if !resources_dir.is_dir() {
+        return Check::warn(
+            name,
+            format!("{} does not exist", resources_dir.display()),
+            "The launcher can't install OpenAsar into this Discord install, Equicord still loads without it.",
+        );
+    }
+
+    if backup_asar.exists() {
+        // electron-hook redirects the launcher's own opens of paths containing
+        // `resources/app.asar`, so read the real file by way of `resources/./app.asar`
+        let installed = resources_dir.join(".").join("app.asar");
+        let patched = files_equal(&installed, &open_asar).unwrap_or(false);
+
+        return if patched {
+            Check::pass(name, "installed")
+        } else {
+            Check::warn(
+                name,
+                format!(
+                    "{} differs from the downloaded OpenAsar",
+                    app_asar.display()
+                ),
+                "Discord or OpenAsar was updated, it is patched again on the next launch.",
+            )
+        };
+    }
+
+    if !app_asar.exists() {
+        return Check::fail(
+            name,
+            format!("{} is missing", app_asar.display()),
+            format!("Reinstall {discord_name}."),
+        );
+    }
+
+    if !is_writable(&resources_dir) {
+        return Check::warn(
+            name,
+            format!("{} is not writable", resources_dir.display()),
+            "OpenAsar can't be installed into a Discord installed by the system package manager.\n\
+            Equicord still loads without it.",
+        );
+    }
+
+    Check::warn(
+        name,
+        "not installed yet",
+        "It is installed on the next launch.",
+    )
+}
+
+/// Whether we may create files in `dir`, asked without actually creating one.
+#[cfg(unix)]
+fn is_writable(dir: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// Whether we may create files in `dir`, asked without actually creating one.
+#[cfg(windows)]
+fn is_writable(dir: &Path) -> bool {
+    std::fs::metadata(dir).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// Check the sandbox of a Flatpak Discord can be launched with our library and files.
+#[cfg(target_os = "linux")]
+fn check_flatpak(discord_name: &str, id: &electron_hook::FlatpakID) -> Check {
+    use electron_hook::FlatpakID;
+
+    let name = format!("{discord_name} Flatpak permissions");
+    let name = name.as_str();
+
+    let installation = match id {
+        FlatpakID::User(_) => "--user",
+        FlatpakID::System(_) => "--system",
+    };
+
+    let output = match std::process::Command::new("flatpak")
+        .args(["info", installation, "--show-permissions", &id.to_string()])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            return Check::fail(
+                name,
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                format!("Reinstall {id} with flatpak."),
+            )
+        }
+        Err(_) => {
+            return Check::fail(
+                name,
+                "flatpak is not installed or not in PATH",
+                "Install flatpak, or install Discord without it.",
+            )
+        }
+    };
+
+    // The launcher grants itself access to what it needs when starting Discord,
+    // but overrides that deny the host or home directory take precedence
+    let permissions = String::from_utf8_lossy(&output.stdout);
+    let denied: Vec<&str> = permissions
+        .lines()
+        .filter_map(|line| line.strip_prefix("filesystems="))
+        .flat_map(|filesystems| filesystems.split(';'))
+        .filter(|filesystem| {
+            matches!(
+                filesystem.trim(),
+                "!host" | "!host-os" | "!home" | "!xdg-cache" | "!xdg-data"
+            )
+        })
+        .collect();
+
+    if !denied.is_empty() {
+        return Check::warn(
+            name,
+            format!("filesystem access denied: {}", denied.join(", ")),
+            format!(
+                "The launcher's library and files may not be readable inside the sandbox.\n\
+                Reset the overrides with `flatpak override {installation} --reset {id}`."
+            ),
+        );
+    }
+
+    Check::pass(name, "ok")
+}
+
+fn check_github() -> Check {
+    let name = "GitHub";
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(GITHUB_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+
+    let response = agent
+        .get(GITHUB_RATE_LIMIT_URL)
+        .header("User-Agent", updater::USER_AGENT)
+        .call();
+
+    let mut response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            return Check::fail(
+                name,
+                format!("api.github.com is unreachable: {e}"),
+                "Check your internet connection, proxy or firewall.\n\
+                The launcher keeps using the cached Equicord files until it can reach GitHub.",
+            )
+        }
+    };
+
+    if response.status() != 200 {
+        return Check::warn(
+            name,
+            format!("api.github.com returned {}", response.status()),
+            "GitHub may be having problems, try again later.",
+        );
+    }
+
+    let rate = response
+        .body_mut()
+        .read_to_string()
+        .ok()
+        .and_then(|body| body.parse::<JsonValue>().ok())
+        .and_then(|json| {
+            let object: &HashMap<_, _> = json.get()?;
+            let rate: &HashMap<_, _> = object.get("rate")?.get()?;
+            let field = |key: &str| rate.get(key)?.get::<f64>().copied();
+
+            Some((field("remaining")?, field("limit")?, field("reset")?))
+        });
+
+    let Some((remaining, limit, reset)) = rate else {
+        return Check::pass(name, "reachable");
+    };
+
+    if remaining < 1.0 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs_f64())
+            .unwrap_or_default();
+        let minutes = ((reset - now) / 60.0).ceil().max(1.0);
+
+        return Check::warn(
+            name,
+            format!("rate limited, 0 of {limit} requests left"),
+            format!(
+                "Updates are skipped until the limit resets in about {minutes} minute(s).\n\
+                Shared networks and VPNs can use the limit up quickly."
+            ),
+        );
+    }
+
+    Check::pass(
+        name,
+        format!("reachable, {remaining} of {limit} requests left this hour"),
+    )
+}
+
+/// Check a tool needed for `--custom` builds, which are optional.
+fn check_tool(program: &str, name: &str, minimum: &str) -> Check {
+    let hint = format!("Only needed for --custom builds. Install {name} {minimum} or newer.");
+
+    let version = match silent_cmd(program).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        _ => return Check::warn(name, "not installed or not in PATH", hint),
+    };
+
+    check_version(name, &version, minimum, hint)
+}
+
+fn check_version(name: &str, version: &str, minimum: &str, hint: String) -> Check {
+    let Some(installed) = Version::find_in(version) else {
+        return Check::pass(name, version);
+    };
+
+    let too_old = Version::parse(minimum)
+        .map(|minimum| !VersionReq::at_least(minimum).matches(&installed))
+        .unwrap_or(false);
+
+    if too_old {
+        Check::warn(name, format!("{installed} is older than {minimum}"), hint)
+    } else {
+        Check::pass(name, installed.to_string())
+    }
+}
+
+fn check_tools() -> Vec<Check> {
+    let mut checks = vec![
+        check_tool("git", "git", constants::MIN_GIT_VERSION),
+        check_tool("node", "Node.js", constants::MIN_NODE_VERSION),
+    ];
+
+    let name = "Package manager";
+    checks.push(match PackageManager::resolve(None) {
+        Ok((package_manager @ (PackageManager::Pnpm | PackageManager::CorepackPnpm), version)) => {
+            check_version(
+                &package_manager.to_string(),
+                &version,
+                constants::MIN_PNPM_VERSION,
+                format!(
+                    "Only needed for --custom builds. Update pnpm to {} or newer.",
+                    constants::MIN_PNPM_VERSION
+                ),
+            )
+        }
+        Ok((package_manager, version)) => Check::pass(name, format!("{package_manager} {version}")),
+        Err(_) => Check::warn(
+            name,
+            "none of pnpm, npm, yarn or bun found",
+            "Only needed for --custom builds. Install pnpm, or Node.js with npm.",
+        ),
+    });
+
+    checks
+}
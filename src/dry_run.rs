@@ -0,0 +1,219 @@
+//! `--dry-run`: work out what launching would do, without changing anything.
+//!
+//! Discovery and the update checks run as usual, but nothing is downloaded, built,
+//! renamed or started. Instead, every step a real launch would take is collected
+//! in a [`Plan`] and printed.
+
+use std::path::Path;
+use std::process::Command;
+
+use log::{info, warn};
+
+use crate::build_log::command_line;
+use crate::builder::{self, BuildOptions};
+use crate::constants;
+use crate::discord::{self, DiscordBranch, DiscordPath};
+use crate::updater::{self, PendingUpdate};
+
+/// The steps a launch would take, in order.
+#[derive(Default)]
+pub struct Plan {
+    steps: Vec<String>,
+}
+
+impl Plan {
+    pub fn download(&mut self, url: &str, dest: &Path) {
+        self.steps
+            .push(format!("download {url}\nto {}", dest.display()));
+    }
+
+    /// Run `cmd`, with its environment variables and working directory.
+    pub fn run(&mut self, cmd: &Command) {
+        self.run_if(cmd, None);
+    }
+
+    /// Run `cmd` only if `condition` turns out to hold once the earlier steps are done.
+    pub fn run_if(&mut self, cmd: &Command, condition: Option<&str>) {
+        let mut step = "run ".to_string();
+        for (key, value) in cmd.get_envs() {
+            if let Some(value) = value {
+                step.push_str(&format!(
+                    "{}={} ",
+                    key.to_string_lossy(),
+                    value.to_string_lossy()
+                ));
+            }
+        }
+        step.push_str(&command_line(cmd));
+
+        if let Some(dir) = cmd.get_current_dir() {
+            step.push_str(&format!("\nin {}", dir.display()));
+        }
+        if let Some(condition) = condition {
+            step.push_str(&format!("\nonly if {condition}"));
+        }
+
+        self.steps.push(step);
+    }
+
+    /// Remove `path`, only if `condition` holds like [`run_if`](Self::run_if).
+    pub fn remove_if(&mut self, path: &Path, condition: Option<&str>) {
+        let mut step = format!("remove {}", path.display());
+        if let Some(condition) = condition {
+            step.push_str(&format!("\nonly if {condition}"));
+        }
+
+        self.steps.push(step);
+    }
+
+    pub fn copy(&mut self, from: &Path, to: &Path) {
+        self.steps
+            .push(format!("copy {}\nto {}", from.display(), to.display()));
+    }
+
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        self.steps
+            .push(format!("rename {}\nto {}", from.display(), to.display()));
+    }
+
+    pub fn write(&mut self, path: &Path) {
+        self.steps.push(format!("write {}", path.display()));
+    }
+
+    pub fn launch(&mut self, description: String) {
+        self.steps.push(format!("launch {description}"));
+    }
+
+    /// Add the downloads of an update found by the updater, and its release info file.
+    fn update(&mut self, update: &PendingUpdate, assets_dir: &Path, release_file: &str) {
+        for (name, url) in &update.files {
+            self.download(url, &assets_dir.join(name));
+        }
+        self.write(&assets_dir.join(release_file));
+    }
+
+    /// Number the steps, indenting their continuation lines.
+    fn format(&self) -> String {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let number = format!("{:>3}. ", index + 1);
+                let indent = " ".repeat(number.len());
+                format!("{number}{}\n", step.replace('\n', &format!("\n{indent}")))
+            })
+            .collect()
+    }
+}
+
+/// Print what launching `branch` would do.
+///
+/// `local` and `build_options` are the `--local` and `--custom` options, as for a launch.
+pub async fn run(
+    branch: DiscordBranch,
+    local: Option<&str>,
+    build_options: &BuildOptions,
+    launch_args: &[String],
+) -> Result<(), String> {
+    let Some(discord_dir) = discord::find_discord(branch)? else {
+        return Err(format!(
+            "No {} installation found, nothing would be launched.",
+            branch.display_name()
+        ));
+    };
+
+    match &discord_dir {
+        DiscordPath::Filesystem(executable) => {
+            info!(
+                "Found {} at {}",
+                branch.display_name(),
+                executable.display()
+            )
+        }
+        DiscordPath::FlatpakId(id) => info!("Found {} as Flatpak {id}", branch.display_name()),
+    }
+
+    let assets_dir = constants::asset_cache_path()
+        .ok_or_else(|| "Failed to determine asset cache directory".to_string())?;
+    let mut plan = Plan::default();
+
+    // Always checked, like a launch does
+    let open_asar_update = updater::check_open_asar().await;
+    match &open_asar_update {
+        Some(Some(update)) => {
+            plan.update(update, &assets_dir, constants::OPEN_ASAR_RELEASE_INFO_FILE)
+        }
+        Some(None) => {}
+        None => warn!("Would keep using the cached OpenAsar, if any."),
+    }
+
+    let mod_entrypoint = match local {
+        Some(local_path) => {
+            info!("Would use the local mod entrypoint.");
+            local_path.to_string()
+        }
+        None => {
+            if !build_options.userplugin_sources.is_empty() {
+                if let Err(e) = builder::plan_custom_build(build_options, &mut plan) {
+                    warn!("The custom build would fail: {e}");
+                    warn!("Would fall back to the cached build, if any.");
+                }
+            } else {
+                match updater::check_assets().await {
+                    Some(Some(update)) => {
+                        plan.update(&update, &assets_dir, constants::RELEASE_INFO_FILE)
+                    }
+                    Some(None) => {}
+                    None => warn!("Would keep using the cached Equicord files, if any."),
+                }
+            }
+
+            assets_dir
+                .join(constants::MOD_ENTRYPOINT)
+                .to_string_lossy()
+                .to_string()
+        }
+    };
+
+    let library_path = constants::get_library_path();
+    let args: String = launch_args.iter().map(|arg| format!(" {arg}")).collect();
+
+    match &discord_dir {
+        DiscordPath::Filesystem(discord_exe) => {
+            // Mirrors installing OpenAsar in `launch`
+            let open_asar_source = assets_dir.join(constants::OPEN_ASAR_FILENAME);
+            let open_asar_available =
+                open_asar_source.exists() || matches!(open_asar_update, Some(Some(_)));
+
+            if let (true, Some(parent)) = (open_asar_available, discord_exe.parent()) {
+                let resources_dir = parent.join("resources");
+                let app_asar = resources_dir.join("app.asar");
+                let backup_asar = resources_dir.join("_app.asar");
+
+                let backup_exists = backup_asar.exists();
+                if !backup_exists && app_asar.exists() {
+                    plan.rename(&app_asar, &backup_asar);
+                }
+                if backup_exists || app_asar.exists() {
+                    plan.copy(&open_asar_source, &app_asar);
+                }
+            }
+
+            plan.launch(format!(
+                "{}{args}\nwith {library_path}\nloading {mod_entrypoint}",
+                discord_exe.display()
+            ));
+        }
+        DiscordPath::FlatpakId(id) => plan.launch(format!(
+            "flatpak run {id}{args}\nwith {library_path}\nloading {mod_entrypoint}"
+        )),
+    }
+
+    println!(
+        "Launching {} would take these steps, nothing was changed:",
+        branch.display_name()
+    );
+    print!("{}", plan.format());
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Machine-readable events for `--output json`, used by wrappers such as tray apps.
+//!
+//! Each event is a JSON object on its own line on stdout, with an `event` field naming
+//! it. Human-readable messages go to stderr instead while events are enabled.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tinyjson::JsonValue;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Print events on stdout from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether events are printed, i.e. the launcher was started with `--output json`.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+pub enum Event<'a> {
+    /// The result of looking for the Discord install, `path` being `None` if none was found.
+    /// `kind` is `filesystem` or `flatpak`, with the Flatpak app ID as `path`.
+    Discovery {
+        branch: &'a str,
+        kind: Option<&'a str>,
+        path: Option<String>,
+    },
+    /// Progress of an update check for `target`, `equicord` or `openasar`.
+    /// `status` is one of `checking`, `up_to_date`, `update_available`, `updated` and `failed`.
+    UpdateCheck {
+        target: &'a str,
+        status: &'a str,
+        version: Option<&'a str>,
+    },
+    /// A file of an update finished downloading.
+    Download {
+        target: &'a str,
+        name: &'a str,
+        bytes: usize,
+        completed: usize,
+        total: usize,
+    },
+    /// A custom build moved on to `step` (counting from 1) of `total`.
+    BuildStep {
+        step: u32,
+        total: u32,
+        status: &'a str,
+    },
+    /// A custom build finished, `error` being set if it failed.
+    BuildFinished {
+        error: Option<&'a str>,
+        dropped_plugins: &'a [(String, String)],
+        log_path: Option<&'a Path>,
+    },
+    /// Discord was started. The launcher exits after this event, except with `--watch`,
+    /// where Discord is restarted after every rebuild and this event follows each restart.
+    Launched { pid: u32 },
+    /// A warning or error, also printed to stderr.
+    Log { level: &'a str, message: String },
+}
+
+fn string(value: &str) -> JsonValue {
+    JsonValue::String(value.to_string())
+}
+
+fn optional(value: Option<&str>) -> JsonValue {
+    value.map(string).unwrap_or(JsonValue::Null)
+}
+
+fn number(value: impl Into<f64>) -> JsonValue {
+    JsonValue::Number(value.into())
+}
+
+impl Event<'_> {
+    fn name_and_fields(&self) -> (&'static str, Vec<(&'static str, JsonValue)>) {
+        match self {
+            Event::Discovery { branch, kind, path } => (
+                "discovery",
+                vec![
+                    ("branch", string(branch)),
+                    ("kind", optional(*kind)),
+                    ("path", optional(path.as_deref())),
+                ],
+            ),
+            Event::UpdateCheck {
+                target,
+                status,
+                version,
+            } => (
+                "update_check",
+                vec![
+                    ("target", string(target)),
+                    ("status", string(status)),
+                    ("version", optional(*version)),
+                ],
+            ),
+            Event::Download {
+                target,
+                name,
+                bytes,
+                completed,
+                total,
+            } => (
+                "download",
+                vec![
+                    ("target", string(target)),
+                    ("name", string(name)),
+                    ("bytes", number(*bytes as f64)),
+                    ("completed", number(*completed as f64)),
+                    ("total", number(*total as f64)),
+                ],
+            ),
+            Event::BuildStep {
+                step,
+                total,
+                status,
+            } => (
+                "build_step",
+                vec![
+                    ("step", number(*step)),
+                    ("total", number(*total)),
+                    ("status", string(status)),
+                ],
+            ),
+            Event::BuildFinished {
+                error,
+                dropped_plugins,
+                log_path,
+            } => (
+                "build_finished",
+                vec![
+                    ("success", JsonValue::Boolean(error.is_none())),
+                    ("error", optional(*error)),
+                    (
+                        "dropped_plugins",
+                        JsonValue::Array(
+                            dropped_plugins
+                                .iter()
+                                .map(|(plugin, reason)| {
+                                    JsonValue::Object(
+                                        [
+                                            ("name".to_string(), string(plugin)),
+                                            ("reason".to_string(), string(reason)),
+                                        ]
+                                        .into(),
+                                    )
+                                })
+                                .collect(),
+                        ),
+                    ),
+                    (
+                        "log_path",
+                        optional(log_path.map(|path| path.to_string_lossy()).as_deref()),
+                    ),
+                ],
+            ),
+            Event::Launched { pid } => ("launched", vec![("pid", number(*pid))]),
+            Event::Log { level, message } => (
+                "log",
+                vec![("level", string(level)), ("message", string(message))],
+            ),
+        }
+    }
+
+    /// Serialize the event, keeping `event` as the first field.
+    fn to_json(&self) -> String {
+        let (name, fields) = self.name_and_fields();
+
+        let mut json = format!("{{\"event\":\"{name}\"");
+        for (key, value) in fields {
+            let value = value.stringify().unwrap_or_else(|_| "null".to_string());
+            json.push_str(&format!(",\"{key}\":{value}"));
+        }
+        json.push('}');
+
+        json
+    }
+}
+
+/// Print `event` if events are enabled.
+pub fn emit(event: Event) {
+    if !enabled() {
+        return;
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", event.to_json());
+    let _ = stdout.flush();
+}
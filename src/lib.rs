@@ -1,10 +1,28 @@
 // For compiling the modloader DLL:
 pub use electron_hook::*;
 
+pub mod build_log;
 pub mod builder;
+pub mod cache;
+pub mod config;
 pub mod constants;
 pub mod discord;
+pub mod doctor;
+pub mod dry_run;
+pub mod events;
+pub mod lock;
+pub mod logging;
+pub mod package_manager;
+pub mod patches;
+pub mod process;
+pub mod reporter;
+pub mod sources;
+pub mod support_bundle;
 pub mod updater;
+pub mod userplugins;
+pub mod version;
+pub mod watch;
+pub mod zip;
 
 // Library for the binaries to use:
 #[cfg(windows)]
@@ -17,23 +35,127 @@ pub mod progress;
 pub use windows::*;
 
 use clap::Parser;
-use discord::{DiscordBranch, DiscordPath};
+use discord::{DiscordBranch, DiscordOverride, DiscordPath};
+use events::Event;
+use log::{debug, error, info, warn};
+use package_manager::PackageManager;
 
 #[derive(clap::Parser, Debug)]
 struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// To use a local instance of the mod, pass the path to the mod entrypoint.
     ///
     /// e.g. `--local "C:\\Users\\megu\\equicord\\dist\\injector.js"`
     #[clap(short, long)]
     pub local: Option<String>,
 
-    /// Build Equicord with userplugins from the given directory.
+    /// Build Equicord with userplugins from the given directory or git URL.
     /// The launcher will clone the Equicord repo, copy your userplugins in,
     /// and build a custom bundle automatically.
     ///
+    /// Git URLs may end in `#<branch|tag|commit>` and are kept up to date
+    /// automatically. Pass the flag multiple times to combine sources.
+    ///
     /// e.g. `--custom "C:\\Users\\megu\\my-userplugins"`
+    /// or `--custom "https://github.com/me/my-userplugins.git#main"`
     #[clap(short, long)]
-    pub custom: Option<String>,
+    pub custom: Vec<String>,
+
+    /// Watch the local `--custom` directories, rebuilding and restarting
+    /// Discord whenever a userplugin changes.
+    #[clap(short, long, requires = "custom")]
+    pub watch: bool,
+
+    /// Build without userplugins that fail validation, e.g. because they lack an
+    /// `index.ts` or shadow a built-in plugin, instead of failing the `--custom` build.
+    #[clap(long, requires = "custom")]
+    pub skip_invalid_plugins: bool,
+
+    /// Package manager used to build `--custom` bundles.
+    /// By default the first one found of pnpm, corepack pnpm, bun, yarn and npm is used.
+    #[clap(long, value_enum)]
+    pub package_manager: Option<PackageManager>,
+
+    /// Apply the `.patch` files in this directory to Equicord, in file name order,
+    /// before building with `--custom`.
+    #[clap(long, value_name = "DIR", requires = "custom")]
+    pub patches: Option<std::path::PathBuf>,
+
+    /// Make a development build with `--custom`, which enables dev-only plugins
+    /// and skips minification.
+    #[clap(long, requires = "custom")]
+    pub dev: bool,
+
+    /// Extra argument for Equicord's build script. Can be passed multiple times.
+    ///
+    /// e.g. `--build-arg=--standalone`
+    #[clap(
+        long,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        requires = "custom"
+    )]
+    pub build_arg: Vec<String>,
+
+    /// Extra environment variable for Equicord's build script, as `KEY=VALUE`.
+    /// Can be passed multiple times.
+    #[clap(long, value_name = "KEY=VALUE", value_parser = parse_env_var, requires = "custom")]
+    pub build_env: Vec<(String, String)>,
+
+    /// Seconds each git command of a `--custom` build may take before it is cancelled.
+    /// 0 disables the timeout.
+    #[clap(long, value_name = "SECS", default_value_t = constants::DEFAULT_GIT_TIMEOUT_SECS)]
+    pub git_timeout: u64,
+
+    /// Seconds installing dependencies of a `--custom` build may take before it is cancelled.
+    /// 0 disables the timeout.
+    #[clap(long, value_name = "SECS", default_value_t = constants::DEFAULT_INSTALL_TIMEOUT_SECS)]
+    pub install_timeout: u64,
+
+    /// Seconds building a `--custom` bundle may take before it is cancelled.
+    /// 0 disables the timeout.
+    #[clap(long, value_name = "SECS", default_value_t = constants::DEFAULT_BUILD_TIMEOUT_SECS)]
+    pub build_timeout: u64,
+
+    /// Discord branch to launch. Defaults to the one in the launcher's file name,
+    /// e.g. `equicord-canary`, or stable.
+    #[clap(long, value_enum, global = true)]
+    pub branch: Option<DiscordBranch>,
+
+    /// Use the Discord executable, or the install directory containing it, at this path
+    /// instead of looking for one. Can also be set per branch in `config.json`.
+    #[clap(long, value_name = "PATH", global = true, conflicts_with = "flatpak")]
+    pub discord_path: Option<std::path::PathBuf>,
+
+    /// Launch the Flatpak with this app ID instead of looking for Discord,
+    /// e.g. `com.discordapp.DiscordCanary`. Can also be set per branch in `config.json`.
+    #[clap(long, value_name = "APP_ID", global = true)]
+    pub flatpak: Option<String>,
+
+    /// Keep all launcher files (downloads, builds, logs) in this directory.
+    /// Can also be set with the `EQUICORD_LAUNCHER_HOME` environment variable.
+    #[clap(long, value_name = "DIR", global = true)]
+    pub data_dir: Option<std::path::PathBuf>,
+
+    /// Print more details, `-vv` for even more. The log file always has debug details.
+    #[clap(short, long, action = clap::ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only print warnings and errors, `-qq` for only errors.
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// Print newline-delimited JSON events on stdout instead of showing dialogs,
+    /// for wrappers such as tray apps. Messages are printed to stderr instead.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Human)]
+    pub output: OutputFormat,
+
+    /// Check for updates and print everything launching would download, run, rename
+    /// and copy, without changing anything or starting Discord.
+    #[clap(long, conflicts_with_all = ["watch", "output"])]
+    pub dry_run: bool,
 
     /// Optional launch arguments to pass to the Discord executable
     ///
@@ -42,67 +164,246 @@ struct Args {
     pub launch_args: Vec<String>,
 }
 
-pub async fn launch(instance_id: &str, branch: DiscordBranch, display_name: &str) {
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Print the log of the last `--custom` build.
+    Logs {
+        /// Only print the path of the log file.
+        #[clap(long)]
+        path: bool,
+        /// Print the launcher's own log instead.
+        #[clap(long)]
+        launcher: bool,
+    },
+    /// Check for common reasons Equicord doesn't load.
+    Doctor,
+    /// Zip up logs, state files and diagnostics to attach to an issue.
+    SupportBundle {
+        /// Where to write the zip, instead of the current directory.
+        #[clap(long, short)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Inspect or clean the launcher's cached files.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CacheCommand {
+    /// List the cached files.
+    List,
+    /// Print how much space each part of the cache uses.
+    Size,
+    /// Delete cached files. They are downloaded or rebuilt on the next launch.
+    #[command(group(clap::ArgGroup::new("targets").required(true).multiple(true)))]
+    Clean {
+        /// The Equicord and OpenAsar files Discord loads.
+        #[clap(long, group = "targets")]
+        assets: bool,
+        /// Custom build state and output, forcing the next `--custom` build.
+        #[clap(long, group = "targets")]
+        builds: bool,
+        /// The Equicord repo and git userplugin sources.
+        #[clap(long, group = "targets")]
+        repo: bool,
+        /// Everything.
+        #[clap(long, group = "targets")]
+        all: bool,
+        /// Delete the assets even while Discord is running, and wait for another
+        /// launcher instance using the cache instead of failing.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Check that the cached files are complete.
+    Verify,
+}
+
+/// Run the launcher for the branch given by `--branch`, the name it was started as,
+/// or `default_branch`, in that order.
+pub async fn launch(default_branch: DiscordBranch) {
     std::env::set_var("DISABLE_UPDATER_AUTO_PATCHING", "true");
 
     let args = Args::parse();
+    let branch = args
+        .branch
+        .or_else(|| {
+            std::env::args()
+                .next()
+                .and_then(|program| DiscordBranch::from_program_name(&program))
+        })
+        .unwrap_or(default_branch);
+    let display_name = branch.display_name();
+
+    if let Some(path) = &args.discord_path {
+        discord::set_override(branch, DiscordOverride::Path(path.clone()));
+    } else if let Some(app_id) = &args.flatpak {
+        discord::set_override(branch, DiscordOverride::Flatpak(app_id.clone()));
+    }
+
+    if let Some(data_dir) = &args.data_dir {
+        constants::set_data_dir(data_dir);
+    }
+
+    if args.output == OutputFormat::Json {
+        events::enable();
+    }
+
+    // A dry run leaves no trace, not even in the launcher log
+    logging::init(
+        args.verbose.min(2) as i8 - args.quiet.min(2) as i8,
+        !args.dry_run,
+    );
 
-    let Some(discord_dir) = discord::get_discord(branch) else {
+    #[cfg(target_os = "linux")]
+    if !args.dry_run {
+        constants::migrate_legacy_data_dir();
+    }
+
+    if let Some(command) = args.command {
+        #[cfg(windows)]
+        attach_console();
+
+        run_command(command, branch);
+        return;
+    }
+
+    let build_options = builder::BuildOptions {
+        userplugin_sources: args.custom.clone(),
+        update_repos: true,
+        skip_invalid_plugins: args.skip_invalid_plugins,
+        package_manager: args.package_manager,
+        patches_dir: args.patches.clone(),
+        dev: args.dev,
+        build_args: args.build_arg.clone(),
+        build_env: args.build_env.clone(),
+        timeouts: builder::StepTimeouts {
+            git: timeout(args.git_timeout),
+            install: timeout(args.install_timeout),
+            build: timeout(args.build_timeout),
+        },
+    };
+
+    if args.dry_run {
+        let result = dry_run::run(
+            branch,
+            args.local.as_deref(),
+            &build_options,
+            &args.launch_args,
+        )
+        .await;
+
+        if let Err(e) = result {
+            error!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let discord_dir = match discord::find_discord(branch) {
+        Ok(discord_dir) => discord_dir,
+        Err(e) => {
+            error!("{e}");
+            show_dialog(
+                &format!("Can't launch {display_name}"),
+                &format!("Equicord couldn't use the Discord install you chose:\n{e}"),
+                true,
+            );
+            return;
+        }
+    };
+    events::emit(Event::Discovery {
+        branch: branch.id(),
+        kind: discord_dir.as_ref().map(|path| match path {
+            DiscordPath::Filesystem(_) => "filesystem",
+            DiscordPath::FlatpakId(_) => "flatpak",
+        }),
+        path: discord_dir.as_ref().map(|path| match path {
+            DiscordPath::Filesystem(executable) => executable.to_string_lossy().to_string(),
+            DiscordPath::FlatpakId(id) => id.to_string(),
+        }),
+    });
+
+    let Some(discord_dir) = discord_dir else {
         let title = format!("No {display_name} installation found!");
         let message = format!(
             "Equicord couldn't find your Discord installation.\n\
 			Try reinstalling {display_name} and try again."
         );
-
-        #[cfg(not(windows))]
-        {
-            use dialog::DialogBox as _;
-            let _ = dialog::Message::new(message).title(title).show();
-        }
-
-        #[cfg(windows)]
-        messagebox(&title, &message, MessageBoxIcon::Error);
+        show_dialog(&title, &message, true);
 
         return;
     };
 
     let library_path = constants::get_library_path();
+    debug!("Using library {library_path}");
+
+    if !args.custom.is_empty() {
+        // Ctrl-C cancels a running build instead of leaving its processes behind
+        process::handle_ctrl_c();
+    }
 
     let assets_dir = constants::asset_cache_dir().unwrap();
 
+    // Only one launcher instance updates the cache or builds at a time,
+    // the others wait and then reuse the result
+    let cache_lock = match lock::CacheLock::acquire() {
+        Ok(cache_lock) => Some(cache_lock),
+        Err(e) => {
+            warn!("{e}");
+            warn!("Skipping updates and using the cached files as they are.");
+            None
+        }
+    };
+
     // Always check for OpenAsar updates regardless of mode
-    let _ = updater::download_open_asar().await;
+    if cache_lock.is_some() {
+        let _ = updater::download_open_asar().await;
+    }
 
     // Determine mod entrypoint based on mode: --local, --custom, or default (download)
     let mod_entrypoint = if let Some(local_path) = args.local {
         // --local: Use a pre-built local mod entrypoint directly
         local_path
-    } else if let Some(custom_dir) = args.custom {
-        // --custom: Build Equicord with userplugins from the given directory
-        match builder::run_custom_build(&custom_dir) {
-            Ok(()) => {
-                println!("[Equicord Launcher] Custom build succeeded.");
+    } else if !args.custom.is_empty() {
+        // --custom: Build Equicord with userplugins from the given sources
+        let build_result = match cache_lock {
+            Some(_) => builder::run_custom_build(&build_options),
+            None => Err("Another launcher instance is still updating the cache.".to_string()),
+        };
+
+        match build_result {
+            Ok(outcome) => {
+                info!("Custom build succeeded.");
+                events::emit(Event::BuildFinished {
+                    error: None,
+                    dropped_plugins: &outcome.dropped_plugins,
+                    log_path: outcome.log_path.as_deref(),
+                });
+
+                report_dropped_plugins(&outcome);
             }
             Err(e) => {
-                eprintln!("[Equicord Launcher] Custom build failed: {e}");
+                error!("Custom build failed: {e}");
+                events::emit(Event::BuildFinished {
+                    error: Some(&e),
+                    dropped_plugins: &[],
+                    log_path: build_log::latest_build_log().as_deref(),
+                });
 
-                #[cfg(not(windows))]
-                {
-                    use dialog::DialogBox as _;
-                    let _ = dialog::Message::new(format!(
-                        "Custom build failed:\n{e}\n\nFalling back to cached build if available."
-                    ))
-                    .title("Equicord Build Error")
-                    .show();
-                }
-
-                #[cfg(windows)]
-                messagebox(
+                show_dialog(
                     "Equicord Build Error",
                     &format!(
                         "Custom build failed:\n{e}\n\nFalling back to cached build if available."
                     ),
-                    MessageBoxIcon::Warning,
+                    false,
                 );
             }
         }
@@ -116,7 +417,9 @@ pub async fn launch(instance_id: &str, branch: DiscordBranch, display_name: &str
     } else {
         // Default: Download pre-built assets from GitHub
         // We can usually attempt to run Discord even if the downloads fail...
-        let _ = updater::download_assets().await;
+        if cache_lock.is_some() {
+            let _ = updater::download_assets().await;
+        }
 
         assets_dir
             .join(constants::MOD_ENTRYPOINT)
@@ -125,66 +428,257 @@ pub async fn launch(instance_id: &str, branch: DiscordBranch, display_name: &str
             .to_string()
     };
 
-    let branch_name = match branch {
-        DiscordBranch::Stable => "stable",
-        DiscordBranch::PTB => "ptb",
-        DiscordBranch::Canary => "canary",
-        DiscordBranch::Development => "development",
-    };
+    drop(cache_lock);
 
     let asar = electron_hook::asar::Asar::new()
-        .with_id(instance_id)
+        .with_id(branch.instance_id())
         .with_mod_entrypoint(&mod_entrypoint)
         .with_template(include_str!("./require.js"))
-        .with_wm_class(&format!("equicord-{branch_name}"))
+        .with_wm_class(&format!("equicord-{}", branch.id()))
         .create()
         .unwrap();
 
     let asar_path = asar.to_string_lossy().to_string();
+    debug!("Using mod entrypoint {mod_entrypoint} and asar {asar_path}");
 
-    match discord_dir {
-        DiscordPath::Filesystem(discord_exe) => {
-            // Check if we have OpenAsar downloaded
-            let open_asar_source = constants::asset_cache_dir()
-                .unwrap()
-                .join(constants::OPEN_ASAR_FILENAME);
-
-            if open_asar_source.exists() {
-                if let Some(parent) = discord_exe.parent() {
-                    let resources_dir = parent.join("resources");
-                    let app_asar = resources_dir.join("app.asar");
-                    let backup_asar = resources_dir.join("_app.asar");
-
-                    // If backup doesn't exist, create it by renaming app.asar
-                    if !backup_asar.exists() && app_asar.exists() {
-                        println!("[Equicord Launcher] Backing up original app.asar...");
-                        let _ = std::fs::rename(&app_asar, &backup_asar);
-                    }
+    if let DiscordPath::Filesystem(discord_exe) = &discord_dir {
+        // Check if we have OpenAsar downloaded
+        let open_asar_source = constants::asset_cache_dir()
+            .unwrap()
+            .join(constants::OPEN_ASAR_FILENAME);
 
-                    // Copy OpenAsar to app.asar
-                    // We only do this if we successfully created a backup or if a backup already exists
-                    if backup_asar.exists() {
-                        println!("[Equicord Launcher] Patching OpenAsar...");
-                        let _ = std::fs::copy(&open_asar_source, &app_asar);
-                    }
+        if open_asar_source.exists() {
+            if let Some(parent) = discord_exe.parent() {
+                let resources_dir = parent.join("resources");
+                let app_asar = resources_dir.join("app.asar");
+                let backup_asar = resources_dir.join("_app.asar");
+
+                // If backup doesn't exist, create it by renaming app.asar
+                if !backup_asar.exists() && app_asar.exists() {
+                    info!("Backing up original app.asar...");
+                    let _ = std::fs::rename(&app_asar, &backup_asar);
+                }
+
+                // Copy OpenAsar to app.asar
+                // We only do this if we successfully created a backup or if a backup already exists
+                if backup_asar.exists() {
+                    info!("Patching OpenAsar...");
+                    let _ = std::fs::copy(&open_asar_source, &app_asar);
                 }
             }
+        }
+    }
+
+    if args.watch {
+        // --watch: Keep rebuilding and restart Discord whenever the userplugins change
+        let result = watch::watch(
+            &build_options,
+            || {
+                let pid = start_discord(
+                    &discord_dir,
+                    &library_path,
+                    &asar_path,
+                    args.launch_args.clone(),
+                    true,
+                )?;
+                if let Some(pid) = pid {
+                    events::emit(Event::Launched { pid });
+                }
+                Ok(pid)
+            },
+            |pid| stop_discord(&discord_dir, pid),
+            report_dropped_plugins,
+        );
+
+        if let Err(e) = result {
+            error!("Watch mode stopped: {e}");
+        }
+
+        return;
+    }
+
+    // Wrappers only need to know that Discord started, so don't wait for it to exit
+    let pid = start_discord(
+        &discord_dir,
+        &library_path,
+        &asar_path,
+        args.launch_args,
+        events::enabled(),
+    )
+    .unwrap();
+
+    if let Some(pid) = pid {
+        events::emit(Event::Launched { pid });
+    }
+}
+
+/// Warn about the userplugins a custom build left out, if any.
+fn report_dropped_plugins(outcome: &builder::BuildOutcome) {
+    if outcome.dropped_plugins.is_empty() {
+        return;
+    }
+
+    let plugins = outcome
+        .dropped_plugins
+        .iter()
+        .map(|(plugin, reason)| format!("{plugin}: {reason}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut message = format!("Equicord was built without these userplugins:\n{plugins}");
+    if let Some(path) = &outcome.log_path {
+        message.push_str(&format!("\n\nBuild log: {}", path.display()));
+    }
+
+    warn!("{message}");
+    show_dialog("Equicord Build Warning", &message, false);
+}
+
+/// Show a message box, unless events are printed for a wrapper instead.
+#[cfg_attr(not(windows), allow(unused_variables))]
+fn show_dialog(title: &str, message: &str, error: bool) {
+    if events::enabled() {
+        return;
+    }
+
+    #[cfg(not(windows))]
+    {
+        use dialog::DialogBox as _;
+        let _ = dialog::Message::new(message).title(title).show();
+    }
 
+    #[cfg(windows)]
+    messagebox(
+        title,
+        message,
+        if error {
+            MessageBoxIcon::Error
+        } else {
+            MessageBoxIcon::Warning
+        },
+    );
+}
+
+fn run_command(command: Command, branch: DiscordBranch) {
+    match command {
+        Command::Logs { path, launcher } => {
+            let log_path = if launcher {
+                logging::launcher_log()
+            } else {
+                build_log::latest_build_log()
+            };
+            let Some(log_path) = log_path else {
+                if launcher {
+                    warn!("No launcher log found.");
+                } else {
+                    warn!("No build log found, run a --custom build first.");
+                }
+                return;
+            };
+
+            if path {
+                println!("{}", log_path.display());
+                return;
+            }
+
+            match std::fs::read(&log_path) {
+                Ok(contents) => print!("{}", String::from_utf8_lossy(&contents)),
+                Err(e) => error!(
+                    "Failed to read {}: {e}",
+                    log_path.display()
+                ),
+            }
+        }
+        Command::Doctor => {
+            let checks = doctor::run_checks(branch);
+            print!("{}", doctor::format_checks(&checks));
+
+            if checks
+                .iter()
+                .any(|check| check.status == doctor::Status::Fail)
+            {
+                std::process::exit(1);
+            }
+        }
+        Command::SupportBundle { output } => match support_bundle::create(branch, output) {
+            Ok(path) => info!(
+                "Created {}, attach it to your issue.\n\
+                Home directory paths are replaced with ~, but check it for anything else private first.",
+                path.display()
+            ),
+            Err(e) => {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        },
+        Command::Cache { command } => {
+            let result = match command {
+                CacheCommand::List => cache::list(),
+                CacheCommand::Size => cache::size(),
+                CacheCommand::Clean {
+                    assets,
+                    builds,
+                    repo,
+                    all,
+                    force,
+                } => cache::clean(
+                    cache::CleanTargets {
+                        assets: assets || all,
+                        builds: builds || all,
+                        repo: repo || all,
+                    },
+                    force,
+                ),
+                CacheCommand::Verify => cache::verify().and_then(|problems| {
+                    if problems.is_empty() {
+                        info!("The cache is fine.");
+                        Ok(())
+                    } else {
+                        Err(format!(
+                            "Found {} problem(s) in the cache:\n  {}",
+                            problems.len(),
+                            problems.join("\n  ")
+                        ))
+                    }
+                }),
+            };
+
+            if let Err(e) = result {
+                error!("{e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{var}'")),
+    }
+}
+
+/// Convert a timeout in seconds from the command line, where 0 means no timeout.
+fn timeout(secs: u64) -> Option<std::time::Duration> {
+    (secs > 0).then(|| std::time::Duration::from_secs(secs))
+}
+
+/// Launch Discord with the mod injected. Returns the process ID when `detach` is set.
+fn start_discord(
+    discord_dir: &DiscordPath,
+    library_path: &str,
+    asar_path: &str,
+    launch_args: Vec<String>,
+    detach: bool,
+) -> Result<Option<u32>, String> {
+    match discord_dir {
+        DiscordPath::Filesystem(discord_exe) => {
             let discord_dir = discord_exe.to_string_lossy().to_string();
 
-            electron_hook::launch(
-                &discord_dir,
-                &library_path,
-                &asar_path,
-                args.launch_args,
-                false,
-            )
-            .unwrap();
+            electron_hook::launch(&discord_dir, library_path, asar_path, launch_args, detach)
         }
         #[cfg(target_os = "linux")]
         DiscordPath::FlatpakId(id) => {
-            electron_hook::launch_flatpak(&id, &library_path, &asar_path, args.launch_args, false)
-                .unwrap();
+            electron_hook::launch_flatpak(id, library_path, asar_path, launch_args, detach)
         }
         #[cfg(not(target_os = "linux"))]
         DiscordPath::FlatpakId(_) => {
@@ -192,3 +686,17 @@ pub async fn launch(instance_id: &str, branch: DiscordBranch, display_name: &str
         }
     }
 }
+
+/// Stop a Discord instance started by [`start_discord`].
+fn stop_discord(discord_dir: &DiscordPath, pid: u32) {
+    match discord_dir {
+        DiscordPath::Filesystem(_) => process::kill_process_tree(pid),
+        // `flatpak run` doesn't forward signals into the sandbox
+        DiscordPath::FlatpakId(id) => {
+            let _ = std::process::Command::new("flatpak")
+                .args(["kill", &id.to_string()])
+                .status();
+            process::wait_for_exit(pid);
+        }
+    }
+}
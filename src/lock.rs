@@ -0,0 +1,183 @@
+//! Advisory lock serializing cache updates and custom builds between launcher instances.
+//!
+//! Starting e.g. Stable and Canary at the same time would otherwise have both
+//! download into the same asset cache and build in the same Equicord repo. The second
+//! instance waits for the first and then finds the cache already up to date.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::constants;
+
+/// How long to wait for another instance before giving up.
+/// Long enough for a full custom build with the default step timeouts.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Held while updating the asset cache or building. Released when dropped,
+/// or by the OS if the launcher exits without dropping it.
+pub struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    /// Acquire the lock, waiting for other launcher instances to release it.
+    pub fn acquire() -> Result<CacheLock, String> {
+        let (path, file) = open_lock_file()?;
+
+        let start = Instant::now();
+        let mut announced = false;
+
+        while !try_lock(&file) {
+            if !announced {
+                info!(
+                    "Another launcher instance{} is updating the cache, waiting for it to finish...",
+                    holder(&path)
+                );
+                announced = true;
+            }
+
+            if start.elapsed() > LOCK_TIMEOUT {
+                return Err(format!(
+                    "Timed out after {} minutes waiting for another launcher instance.",
+                    LOCK_TIMEOUT.as_secs() / 60
+                ));
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        if announced {
+            info!("The other launcher instance finished.");
+        }
+
+        Ok(CacheLock::locked(file))
+    }
+
+    /// Acquire the lock if no other launcher instance holds it, without waiting.
+    pub fn try_acquire() -> Result<CacheLock, String> {
+        let (path, file) = open_lock_file()?;
+
+        if !try_lock(&file) {
+            return Err(format!(
+                "Another launcher instance{} is updating the cache.",
+                holder(&path)
+            ));
+        }
+
+        Ok(CacheLock::locked(file))
+    }
+
+    fn locked(mut file: File) -> CacheLock {
+        // Let waiting instances know who they are waiting for
+        let exe_name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| {
+                exe.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_default();
+        let _ = file.set_len(0);
+        let _ = write!(file, "pid {}, {exe_name}", std::process::id());
+        let _ = file.flush();
+
+        CacheLock { file }
+    }
+}
+
+fn open_lock_file() -> Result<(PathBuf, File), String> {
+    let path = constants::lock_file_path()
+        .ok_or_else(|| "Failed to determine lock file path".to_string())?;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| format!("Failed to open lock file {:?}: {e}", path))?;
+
+    Ok((path, file))
+}
+
+/// Who holds the lock, as written by [`CacheLock::locked`], e.g. ` (pid 123, equicord)`.
+fn holder(path: &Path) -> String {
+    let holder = std::fs::read_to_string(path).unwrap_or_default();
+    let holder = holder.trim();
+
+    if holder.is_empty() {
+        String::new()
+    } else {
+        format!(" ({holder})")
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        unlock(&self.file);
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+#[cfg(unix)]
+fn unlock(file: &File) {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe {
+        libc::flock(file.as_raw_fd(), libc::LOCK_UN);
+    }
+}
+
+// Windows locks are mandatory, so lock a byte far past the end of the file
+// to keep its contents readable by waiting instances.
+#[cfg(windows)]
+const LOCK_OFFSET: u32 = u32::MAX;
+
+#[cfg(windows)]
+fn try_lock(file: &File) -> bool {
+    use std::os::windows::io::AsRawHandle;
+
+    use winapi::um::fileapi::LockFileEx;
+    use winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED};
+
+    unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        overlapped.u.s_mut().Offset = LOCK_OFFSET;
+
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            1,
+            0,
+            &mut overlapped,
+        ) != 0
+    }
+}
+
+#[cfg(windows)]
+fn unlock(file: &File) {
+    use std::os::windows::io::AsRawHandle;
+
+    use winapi::um::fileapi::UnlockFileEx;
+    use winapi::um::minwinbase::OVERLAPPED;
+
+    unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        overlapped.u.s_mut().Offset = LOCK_OFFSET;
+
+        UnlockFileEx(file.as_raw_handle() as _, 0, 1, 0, &mut overlapped);
+    }
+}
@@ -0,0 +1,308 @@
+//! The launcher's log, printed to the console and appended to `logs/launcher.log`.
+//!
+//! The binaries are GUI applications on Windows and usually started from a desktop
+//! entry on Linux, so console output is mostly lost. The log file always records
+//! the launcher's own messages down to the debug level, whatever the console shows.
+//!
+//! The console shows info and above by default. `-v`/`-q` raise or lower that, or
+//! `RUST_LOG` sets a filter like `warn,equicord_launcher::builder=debug`.
+//! With `--output json`, warnings and errors are also emitted as [`Event::Log`] events.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::build_log::utc_timestamp;
+use crate::constants;
+use crate::events::{self, Event};
+
+/// Rotate the log file once it grows past this size.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// How many log files are kept, including the current one.
+const MAX_LOG_FILES: usize = 3;
+
+/// Messages from the launcher itself, rather than its dependencies.
+const LAUNCHER_TARGET: &str = "equicord_launcher";
+
+/// The target subprocess output counts as, so `RUST_LOG` can silence it too.
+const BUILD_OUTPUT_TARGET: &str = "equicord_launcher::build_log";
+
+/// Whether subprocess output is echoed to the console, see [`echo_subprocess_output`].
+static ECHO_SUBPROCESS_OUTPUT: AtomicBool = AtomicBool::new(true);
+
+/// A `RUST_LOG`-style filter: a default level and per-target overrides.
+struct Filter {
+    default: LevelFilter,
+    /// Targets with their level, most specific last.
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn level(default: LevelFilter) -> Filter {
+        Filter {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    /// Parse comma-separated `level`, `target` and `target=level` directives.
+    /// Invalid directives are ignored, like `env_logger` does.
+    fn parse(spec: &str) -> Filter {
+        let mut filter = Filter::level(LevelFilter::Error);
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if let Ok(level) = level.trim().parse() {
+                        filter.directives.push((target.trim().to_string(), level));
+                    }
+                }
+                None => match directive.parse() {
+                    Ok(level) => filter.default = level,
+                    Err(_) => filter
+                        .directives
+                        .push((directive.to_string(), LevelFilter::Trace)),
+                },
+            }
+        }
+
+        filter.directives.sort_by_key(|(target, _)| target.len());
+        filter
+    }
+
+    fn enabled(&self, target: &str, level: Level) -> bool {
+        let max_level = self
+            .directives
+            .iter()
+            .rev()
+            .find(|(name, _)| is_in_target(target, name))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default);
+
+        level <= max_level
+    }
+}
+
+/// Whether `target` is `name` or one of its submodules.
+fn is_in_target(target: &str, name: &str) -> bool {
+    target
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+struct Logger {
+    console: Filter,
+    /// Opened on first use, so nothing is created before the data directory is migrated.
+    file: OnceLock<Option<Mutex<File>>>,
+}
+
+impl Logger {
+    fn file(&self) -> Option<&Mutex<File>> {
+        self.file
+            .get_or_init(|| open_log_file().map(Mutex::new))
+            .as_ref()
+    }
+
+    fn file_enabled(&self, target: &str, level: Level) -> bool {
+        (is_in_target(target, LAUNCHER_TARGET) && level <= Level::Debug)
+            || self.console.enabled(target, level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.file_enabled(metadata.target(), metadata.level())
+    }
+
+    fn log(&self, record: &Record) {
+        let (target, level) = (record.target(), record.level());
+
+        if self.console.enabled(target, level) {
+            let label = match level {
+                Level::Error => "Error: ",
+                Level::Warn => "Warning: ",
+                Level::Info => "",
+                Level::Debug => "debug: ",
+                Level::Trace => "trace: ",
+            };
+            let line = format!("[Equicord Launcher] {label}{}", record.args());
+
+            // stdout is reserved for events when they are enabled
+            if level == Level::Info && !events::enabled() {
+                println!("{line}");
+            } else {
+                eprintln!("{line}");
+            }
+        }
+
+        if level <= Level::Warn && is_in_target(target, LAUNCHER_TARGET) {
+            events::emit(Event::Log {
+                level: if level == Level::Error {
+                    "error"
+                } else {
+                    "warning"
+                },
+                message: record.args().to_string(),
+            });
+        }
+
+        if self.file_enabled(target, level) {
+            if let Some(Ok(mut file)) = self.file().map(Mutex::lock) {
+                // Several launcher instances can share the file, so tag lines with the pid
+                let _ = writeln!(
+                    file,
+                    "{} {level:<5} [{}] {target}: {}",
+                    utc_timestamp(),
+                    std::process::id(),
+                    record.args()
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(Ok(mut file)) = self.file.get().and_then(Option::as_ref).map(Mutex::lock) {
+            let _ = file.flush();
+        }
+    }
+}
+
+fn rotated_log_path(logs_dir: &Path, index: usize) -> std::path::PathBuf {
+    if index == 0 {
+        logs_dir.join(constants::LAUNCHER_LOG_FILE)
+    } else {
+        logs_dir.join(format!("launcher.{index}.log"))
+    }
+}
+
+/// Open the log file for appending, rotating it first if it has grown too large.
+fn open_log_file() -> Option<File> {
+    let logs_dir = constants::logs_dir()?;
+    std::fs::create_dir_all(&logs_dir).ok()?;
+
+    let current = rotated_log_path(&logs_dir, 0);
+    if std::fs::metadata(&current).is_ok_and(|metadata| metadata.len() > MAX_LOG_SIZE) {
+        for index in (1..MAX_LOG_FILES).rev() {
+            let _ = std::fs::rename(
+                rotated_log_path(&logs_dir, index - 1),
+                rotated_log_path(&logs_dir, index),
+            );
+        }
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(current)
+        .ok()
+}
+
+/// Start logging. `verbosity` is the number of `-v` flags minus the number of `-q` flags.
+///
+/// A non-zero `verbosity` takes precedence over `RUST_LOG`. Without `log_file`, nothing
+/// is written to the launcher log. Must be called after the data directory is set, since
+/// the log file lives in it.
+pub fn init(verbosity: i8, log_file: bool) {
+    let console = match std::env::var("RUST_LOG") {
+        Ok(spec) if verbosity == 0 && !spec.trim().is_empty() => Filter::parse(&spec),
+        _ => {
+            let level = match verbosity {
+                i8::MIN..=-2 => LevelFilter::Error,
+                -1 => LevelFilter::Warn,
+                0 => LevelFilter::Info,
+                1 => LevelFilter::Debug,
+                2..=i8::MAX => LevelFilter::Trace,
+            };
+
+            // Keep dependencies at info unless asked for with RUST_LOG
+            let mut filter = Filter::level(level.min(LevelFilter::Info));
+            filter.directives.push((LAUNCHER_TARGET.to_string(), level));
+            filter
+        }
+    };
+
+    // Build output is as chatty as info messages, so it's shown along with them
+    ECHO_SUBPROCESS_OUTPUT.store(
+        console.enabled(BUILD_OUTPUT_TARGET, Level::Info),
+        Ordering::Relaxed,
+    );
+
+    let file = OnceLock::new();
+    if !log_file {
+        let _ = file.set(None);
+    }
+
+    let logger = Logger { console, file };
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(LevelFilter::Trace);
+    }
+}
+
+/// Whether the output of git, the package manager and the build should be echoed to
+/// the console. Off with `-q`, it's still written to the build log.
+pub fn echo_subprocess_output() -> bool {
+    ECHO_SUBPROCESS_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// The current launcher log, if anything has been logged yet.
+pub fn launcher_log() -> Option<std::path::PathBuf> {
+    let path = rotated_log_path(&constants::logs_dir()?, 0);
+    path.is_file().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_level() {
+        let filter = Filter::parse("warn");
+        assert!(filter.enabled("equicord_launcher", Level::Warn));
+        assert!(!filter.enabled("equicord_launcher", Level::Info));
+        assert!(filter.enabled("ureq", Level::Error));
+
+        // Without a level, only errors are shown
+        let filter = Filter::parse("");
+        assert!(filter.enabled("ureq", Level::Error));
+        assert!(!filter.enabled("ureq", Level::Warn));
+    }
+
+    #[test]
+    fn target_levels() {
+        let filter =
+            Filter::parse("info, equicord_launcher=warn ,equicord_launcher::builder=trace");
+        assert!(filter.enabled("equicord_launcher::builder", Level::Trace));
+        assert!(filter.enabled("equicord_launcher::builder::retry", Level::Debug));
+        assert!(!filter.enabled("equicord_launcher::updater", Level::Info));
+        assert!(filter.enabled("equicord_launcher", Level::Warn));
+        assert!(filter.enabled("ureq", Level::Info));
+        assert!(!filter.enabled("ureq", Level::Debug));
+    }
+
+    #[test]
+    fn bare_target_enables_everything() {
+        let filter = Filter::parse("equicord_launcher::watch");
+        assert!(filter.enabled("equicord_launcher::watch", Level::Trace));
+        assert!(!filter.enabled("equicord_launcher::builder", Level::Warn));
+    }
+
+    #[test]
+    fn targets_match_whole_modules() {
+        let filter = Filter::parse("error,equicord_launcher::build=debug");
+        assert!(filter.enabled("equicord_launcher::build", Level::Debug));
+        assert!(!filter.enabled("equicord_launcher::build_log", Level::Debug));
+    }
+
+    #[test]
+    fn invalid_levels_are_ignored() {
+        let filter = Filter::parse("warn,equicord_launcher=loud");
+        assert!(filter.directives.is_empty());
+        assert!(!filter.enabled("equicord_launcher", Level::Info));
+    }
+}
@@ -0,0 +1,213 @@
+//! Package managers that can install Equicord's dependencies and build it.
+//!
+//! Equicord itself uses pnpm, but npm, yarn and bun can run the same
+//! `build` script, so `--custom` works with whichever one is installed.
+
+use std::process::Command;
+
+use crate::builder::silent_cmd;
+use crate::constants;
+use crate::version::Version;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum PackageManager {
+    Pnpm,
+    /// pnpm through Node's bundled corepack, for systems without a global pnpm.
+    CorepackPnpm,
+    Npm,
+    Yarn,
+    Bun,
+}
+
+impl PackageManager {
+    /// The order package managers are tried in when none is configured.
+    /// pnpm comes first since Equicord's lockfile is a pnpm lockfile.
+    pub const DETECTION_ORDER: &[PackageManager] = &[
+        PackageManager::Pnpm,
+        PackageManager::CorepackPnpm,
+        PackageManager::Bun,
+        PackageManager::Yarn,
+        PackageManager::Npm,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::CorepackPnpm => "corepack pnpm",
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn => "yarn",
+            PackageManager::Bun => "bun",
+        }
+    }
+
+    /// The executable and the arguments that precede every subcommand.
+    fn program(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            PackageManager::Pnpm => ("pnpm", &[]),
+            PackageManager::CorepackPnpm => ("corepack", &["pnpm"]),
+            PackageManager::Npm => ("npm", &[]),
+            PackageManager::Yarn => ("yarn", &[]),
+            PackageManager::Bun => ("bun", &[]),
+        }
+    }
+
+    /// Create a command running this package manager.
+    pub fn command(&self) -> Command {
+        let (program, prefix) = self.program();
+
+        // npm, yarn and corepack (and pnpm when installed through npm) are
+        // batch scripts on Windows, which need their extension to be found
+        #[cfg(windows)]
+        let program = &if which_exe(program) {
+            program.to_string()
+        } else {
+            format!("{program}.cmd")
+        };
+
+        let mut cmd = silent_cmd(program);
+        cmd.args(prefix);
+
+        // With corepack enabled, npm/yarn/bun would otherwise refuse to run in a
+        // project whose `packageManager` field asks for pnpm
+        if !matches!(self, PackageManager::Pnpm | PackageManager::CorepackPnpm) {
+            cmd.env("COREPACK_ENABLE_STRICT", "0");
+        }
+
+        // Yarn 2+ would otherwise use Plug'n'Play, but the build expects a node_modules
+        if *self == PackageManager::Yarn {
+            cmd.env("YARN_NODE_LINKER", "node-modules");
+        }
+
+        cmd
+    }
+
+    /// Arguments for installing dependencies, given the package manager's `version`.
+    pub fn install_args(&self, version: Option<&Version>) -> &'static [&'static str] {
+        match self {
+            PackageManager::Pnpm | PackageManager::CorepackPnpm => {
+                &["install", "--frozen-lockfile"]
+            }
+            // Equicord only ships a pnpm lockfile, so don't leave other lockfiles behind
+            PackageManager::Npm => &["install", "--no-package-lock"],
+            PackageManager::Yarn if is_yarn_classic(version) => &["install", "--no-lockfile"],
+            // Yarn 2+ has no way to skip the lockfile, see `leftover_lockfile`
+            PackageManager::Yarn => &["install"],
+            PackageManager::Bun => &["install", "--no-save"],
+        }
+    }
+
+    /// A lockfile that installing leaves behind in the repo, to be deleted afterwards.
+    pub fn leftover_lockfile(&self, version: Option<&Version>) -> Option<&'static str> {
+        match self {
+            PackageManager::Yarn if !is_yarn_classic(version) => Some("yarn.lock"),
+            _ => None,
+        }
+    }
+
+    /// Arguments for running Equicord's `build` script.
+    pub fn build_args(&self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Pnpm | PackageManager::CorepackPnpm | PackageManager::Yarn => {
+                &["build"]
+            }
+            PackageManager::Npm | PackageManager::Bun => &["run", "build"],
+        }
+    }
+
+    /// Separator needed between [`build_args`](Self::build_args) and arguments meant
+    /// for the build script itself, if the package manager doesn't forward them as-is.
+    pub fn script_args_separator(&self) -> Option<&'static str> {
+        match self {
+            PackageManager::Npm => Some("--"),
+            _ => None,
+        }
+    }
+
+    /// Get the package manager's version, or `None` if it isn't usable.
+    pub fn version(&self) -> Option<String> {
+        let output = self.command().arg("--version").output().ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Use `configured` if given, otherwise the first available package manager.
+    ///
+    /// Returns the package manager along with its version.
+    pub fn resolve(configured: Option<PackageManager>) -> Result<(PackageManager, String), String> {
+        if let Some(package_manager) = configured {
+            return match package_manager.version() {
+                Some(version) => Ok((package_manager, version)),
+                None => Err(format!(
+                    "'{}' is not installed or not in PATH.\n\
+                    Install it, or pick another package manager with --package-manager.",
+                    package_manager.name()
+                )),
+            };
+        }
+
+        PackageManager::DETECTION_ORDER
+            .iter()
+            .find_map(|package_manager| {
+                package_manager
+                    .version()
+                    .map(|version| (*package_manager, version))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "No package manager found.\n\
+                    The --custom flag requires git, Node.js (>={}), and one of pnpm, npm, yarn or bun.\n\
+                    Please install one of them and try again.",
+                    constants::MIN_NODE_VERSION
+                )
+            })
+    }
+}
+
+impl std::fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Whether `version` is Yarn 1, which unlike later versions still has `--no-lockfile`.
+fn is_yarn_classic(version: Option<&Version>) -> bool {
+    version.is_some_and(|version| version.major == 1)
+}
+
+/// Whether `program.exe` can be found on PATH.
+#[cfg(windows)]
+fn which_exe(program: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+
+    std::env::split_paths(&path).any(|dir| dir.join(format!("{program}.exe")).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yarn_lockfile_handling_depends_on_the_version() {
+        let classic = Version::parse("1.22.22").unwrap();
+        let berry = Version::parse("4.6.0").unwrap();
+        let yarn = PackageManager::Yarn;
+
+        assert_eq!(
+            yarn.install_args(Some(&classic)),
+            ["install", "--no-lockfile"]
+        );
+        assert_eq!(yarn.leftover_lockfile(Some(&classic)), None);
+
+        assert_eq!(yarn.install_args(Some(&berry)), ["install"]);
+        assert_eq!(yarn.leftover_lockfile(Some(&berry)), Some("yarn.lock"));
+        assert_eq!(yarn.leftover_lockfile(None), Some("yarn.lock"));
+
+        assert_eq!(PackageManager::Pnpm.leftover_lockfile(Some(&berry)), None);
+    }
+}
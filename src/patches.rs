@@ -0,0 +1,216 @@
+//! Local patches to Equicord core, applied on top of the repo before custom builds.
+//!
+//! Every `.patch` file in the patches directory is applied with `git apply` in
+//! file name order, so `git format-patch` output works as-is.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::build_log::BuildLog;
+use crate::builder::silent_cmd;
+use crate::userplugins::to_hex;
+
+/// The `.patch` files in `dir`, sorted by file name.
+pub fn find_patches(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.is_dir() {
+        return Err(format!("Patches directory does not exist: {:?}", dir));
+    }
+
+    let mut patches: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read patches directory {:?}: {e}", dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "patch"))
+        .collect();
+
+    patches.sort();
+    Ok(patches)
+}
+
+/// Hash the names and contents of the patches, in the order they are applied.
+///
+/// Returns an empty string if there are no patches, matching build states from before patches.
+pub fn hash_patches(patches: &[PathBuf]) -> Result<String, String> {
+    if patches.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut hasher = Sha256::new();
+
+    for patch in patches {
+        let contents =
+            std::fs::read(patch).map_err(|e| format!("Failed to read patch {:?}: {e}", patch))?;
+
+        hasher.update(file_name(patch).as_bytes());
+        hasher.update([0]);
+        hasher.update(Sha256::digest(&contents));
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Undo previously applied patches by resetting tracked files and removing untracked ones.
+///
+/// Ignored files, such as `node_modules` and the synced userplugins, are kept.
+pub fn restore_repo(
+    repo_dir: &Path,
+    timeout: Option<Duration>,
+    log: &BuildLog,
+) -> Result<(), String> {
+    for (mut cmd, what) in restore_commands(repo_dir) {
+        log.run(&mut cmd, what, timeout)?;
+    }
+
+    Ok(())
+}
+
+/// Whether the repo has changes [`restore_repo`] would undo, e.g. patches applied
+/// for a build that then failed, so no build state recorded them. Assumes there are
+/// if git can't tell.
+pub fn has_local_changes(repo_dir: &Path) -> bool {
+    if !repo_dir.join(".git").exists() {
+        return false;
+    }
+
+    let output = match silent_cmd("git")
+        .args(["status", "--porcelain", "--untracked-files=all"])
+        .current_dir(repo_dir)
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return true,
+    };
+
+    // The synced userplugins are left alone by the restore, so they don't count
+    String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+        let path = line.get(3..).unwrap_or(line).trim_start_matches('"');
+        !path.starts_with("src/userplugins")
+    })
+}
+
+/// The commands run by [`restore_repo`], with what they do.
+pub fn restore_commands(repo_dir: &Path) -> [(Command, &'static str); 2] {
+    let mut reset = silent_cmd("git");
+    reset
+        .args(["reset", "--hard", "--quiet", "HEAD"])
+        .current_dir(repo_dir);
+
+    let mut clean = silent_cmd("git");
+    clean
+        .args(["clean", "-fd", "--quiet", "-e", "src/userplugins"])
+        .current_dir(repo_dir);
+
+    [(reset, "git reset"), (clean, "git clean")]
+}
+
+/// The command applying `patch`, before falling back to a three-way merge.
+pub fn apply_command(repo_dir: &Path, patch: &Path, extra_args: &[&str]) -> Command {
+    let mut cmd = silent_cmd("git");
+    cmd.arg("apply")
+        .args(extra_args)
+        .arg(patch)
+        .current_dir(repo_dir);
+    cmd
+}
+
+/// Apply the patches in order, falling back to a three-way merge for patches that
+/// don't apply cleanly.
+///
+/// Expects a repo without local changes, see [`restore_repo`]. If a patch fails,
+/// the repo is restored and the error names the patch.
+pub fn apply_patches(
+    repo_dir: &Path,
+    patches: &[PathBuf],
+    timeout: Option<Duration>,
+    log: &BuildLog,
+) -> Result<(), String> {
+    for patch in patches {
+        let name = file_name(patch);
+        info!("Applying patch {name}...");
+
+        let apply = |extra_args: &[&str]| {
+            log.run(
+                &mut apply_command(repo_dir, patch, extra_args),
+                &format!("git apply {name}"),
+                timeout,
+            )
+        };
+
+        if apply(&[]).is_ok() {
+            continue;
+        }
+
+        info!("Patch {name} doesn't apply cleanly, trying a three-way merge...");
+
+        if let Err(e) = apply(&["--3way"]) {
+            let _ = restore_repo(repo_dir, timeout, log);
+
+            return Err(format!(
+                "Patch {name} failed to apply to the current Equicord version.\n\
+                It probably needs to be updated for upstream changes.\n{e}"
+            ));
+        }
+    }
+
+    if !patches.is_empty() {
+        info!("Applied {} patch(es).", patches.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(repo_dir: &Path, args: &[&str]) {
+        let status = silent_cmd("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(repo_dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    #[test]
+    fn detects_changes_left_by_patches() {
+        let repo_dir = std::env::temp_dir().join(format!(
+            "equicord-launcher-test-patches-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&repo_dir);
+        std::fs::create_dir_all(repo_dir.join("src/userplugins/myPlugin")).unwrap();
+
+        assert!(!has_local_changes(&repo_dir));
+
+        std::fs::write(repo_dir.join("index.ts"), "upstream").unwrap();
+        git(&repo_dir, &["init", "--quiet"]);
+        git(&repo_dir, &["add", "index.ts"]);
+        git(&repo_dir, &["commit", "--quiet", "-m", "init"]);
+
+        // Synced userplugins are expected to be there
+        std::fs::write(repo_dir.join("src/userplugins/myPlugin/index.ts"), "").unwrap();
+        assert!(!has_local_changes(&repo_dir));
+
+        std::fs::write(repo_dir.join("index.ts"), "patched").unwrap();
+        assert!(has_local_changes(&repo_dir));
+        git(&repo_dir, &["checkout", "--quiet", "index.ts"]);
+
+        std::fs::write(repo_dir.join("added.ts"), "from a patch").unwrap();
+        assert!(has_local_changes(&repo_dir));
+
+        let _ = std::fs::remove_dir_all(&repo_dir);
+    }
+}
@@ -0,0 +1,223 @@
+//! Helpers for stopping processes the launcher started.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+/// How long to wait for a process to exit on its own before giving up on it.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set while a [`CancelScope`] is active.
+static CANCELLABLE: AtomicBool = AtomicBool::new(false);
+
+/// Set when Ctrl-C is pressed inside a [`CancelScope`].
+static CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Marks a long-running operation, such as a custom build, that Ctrl-C should
+/// cancel instead of exiting the launcher. Ends when dropped.
+pub struct CancelScope(());
+
+impl CancelScope {
+    pub fn enter() -> CancelScope {
+        CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+        CANCELLABLE.store(true, Ordering::SeqCst);
+        CancelScope(())
+    }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        CANCELLABLE.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Whether Ctrl-C was pressed during the current [`CancelScope`].
+pub fn cancel_requested() -> bool {
+    CANCEL_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Handle Ctrl-C for the rest of the launcher's lifetime.
+///
+/// Inside a [`CancelScope`] Ctrl-C requests cancellation, anywhere else it exits
+/// the launcher like it would without a handler. Must be called within the Tokio runtime.
+pub fn handle_ctrl_c() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if !CANCELLABLE.load(Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+
+            warn!("Cancelling...");
+            CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Terminate a process and everything it spawned, then wait for it to exit.
+///
+/// On Unix, if the process leads its own process group, the whole group is signalled.
+pub fn kill_process_tree(pid: u32) {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+        let _ = std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .creation_flags(CREATE_NO_WINDOW)
+            .status();
+
+        wait_for_exit(pid);
+    }
+
+    #[cfg(unix)]
+    {
+        // Signal the whole process group if the process leads one, so that
+        // children started through a shell or package manager go with it
+        let signal = |signal: &str| {
+            let group = std::process::Command::new("kill")
+                .args([signal, "--", &format!("-{pid}")])
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+
+            if !group {
+                let _ = std::process::Command::new("kill")
+                    .args([signal, &pid.to_string()])
+                    .status();
+            }
+        };
+
+        signal("-TERM");
+
+        if !wait_for_exit(pid) {
+            signal("-KILL");
+        }
+    }
+}
+
+/// Whether the process is still running. Zombies count as exited.
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+        return false;
+    };
+
+    // The state comes right after the command name, which is wrapped in parentheses
+    // and may itself contain spaces or parentheses.
+    stat.rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .is_some_and(|state| state != "Z" && state != "X")
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(false)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Wait for a process to exit. Returns `false` if it is still running after the timeout.
+pub fn wait_for_exit(pid: u32) -> bool {
+    let start = Instant::now();
+
+    while is_running(pid) {
+        if start.elapsed() > EXIT_TIMEOUT {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    true
+}
+
+/// Whether a process name looks like a Discord client of any branch.
+fn is_discord_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.starts_with("discord") && !name.starts_with("discord-rpc")
+}
+
+/// Process IDs of running Discord clients, of any branch.
+#[cfg(target_os = "linux")]
+pub fn running_discord_pids() -> Vec<u32> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            // The command name is truncated to 15 characters, so check the executable too
+            let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+            let exe = std::fs::read_link(format!("/proc/{pid}/exe"))
+                .ok()
+                .and_then(|exe| {
+                    exe.file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                })
+                .unwrap_or_default();
+
+            is_discord_name(comm.trim()) || is_discord_name(&exe)
+        })
+        .filter(|pid| is_running(*pid))
+        .collect()
+}
+
+#[cfg(windows)]
+pub fn running_discord_pids() -> Vec<u32> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let Ok(output) = std::process::Command::new("tasklist")
+        .args(["/FO", "CSV", "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+    else {
+        return Vec::new();
+    };
+
+    // Lines look like "Discord.exe","1234","Console","1","150,000 K"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split("\",\"").map(|field| field.trim_matches('"'));
+            let (name, pid) = (fields.next()?, fields.next()?);
+            is_discord_name(name).then(|| pid.parse().ok()).flatten()
+        })
+        .collect()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn running_discord_pids() -> Vec<u32> {
+    let Ok(output) = std::process::Command::new("ps")
+        .args(["-axo", "pid=,comm="])
+        .output()
+    else {
+        return Vec::new();
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pid, command) = line.trim().split_once(char::is_whitespace)?;
+            let name = command.trim().rsplit('/').next()?;
+            is_discord_name(name).then(|| pid.parse().ok()).flatten()
+        })
+        .collect()
+}
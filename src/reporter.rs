@@ -0,0 +1,275 @@
+//! Progress reporting for custom builds.
+//!
+//! Windows gets the [`ProgressWindow`](crate::progress::ProgressWindow). Elsewhere a
+//! launcher started from a terminal prints a progress bar, and one started from the
+//! desktop on Linux shows a zenity or kdialog progress dialog, the same tools the
+//! `dialog` crate uses for our message boxes. With `--output json`, steps are
+//! reported as events instead.
+
+use std::io::IsTerminal;
+
+use crate::events::{self, Event};
+
+/// Something that shows how far along a multi-step operation is.
+pub trait ProgressReporter {
+    /// Show that `step` (counting from 1) is in progress, described by `status`.
+    fn update(&self, step: u32, status: &str);
+
+    /// Close the reporter after the last step. Dropping it closes it too, e.g. when a step fails.
+    fn close(self: Box<Self>) {}
+}
+
+/// Pick the best way to report progress for how the launcher was started.
+pub fn select(title: &str, total_steps: u32) -> Box<dyn ProgressReporter> {
+    if events::enabled() {
+        return Box::new(EventProgress { total_steps });
+    }
+
+    #[cfg(windows)]
+    {
+        Box::new(crate::progress::ProgressWindow::new(title, total_steps))
+    }
+
+    #[cfg(not(windows))]
+    {
+        #[cfg(target_os = "linux")]
+        if !std::io::stderr().is_terminal() {
+            if let Some(dialog) = DialogProgress::new(title, total_steps) {
+                return Box::new(dialog);
+            }
+        }
+
+        Box::new(TerminalProgress::new(title, total_steps))
+    }
+}
+
+#[cfg(windows)]
+impl ProgressReporter for crate::progress::ProgressWindow {
+    fn update(&self, step: u32, status: &str) {
+        crate::progress::ProgressWindow::update(self, step, status);
+    }
+
+    fn close(self: Box<Self>) {
+        // Brief pause so user can see the last status
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        crate::progress::ProgressWindow::close(*self);
+    }
+}
+
+/// Emits a [`Event::BuildStep`] per step, for wrappers showing their own progress.
+pub struct EventProgress {
+    total_steps: u32,
+}
+
+impl ProgressReporter for EventProgress {
+    fn update(&self, step: u32, status: &str) {
+        events::emit(Event::BuildStep {
+            step,
+            total: self.total_steps,
+            status,
+        });
+    }
+}
+
+/// Width of the terminal progress bar, in characters.
+const BAR_WIDTH: usize = 24;
+
+/// Prints a line per step to stderr, with a progress bar when it is a terminal.
+///
+/// Steps are printed as separate lines rather than redrawing a single one, since the
+/// build's own output is streamed to the terminal in between.
+pub struct TerminalProgress {
+    total_steps: u32,
+    is_terminal: bool,
+}
+
+impl TerminalProgress {
+    pub fn new(title: &str, total_steps: u32) -> Self {
+        eprintln!("[Equicord Launcher] {title}");
+
+        TerminalProgress {
+            total_steps,
+            is_terminal: std::io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn update(&self, step: u32, status: &str) {
+        let total = self.total_steps.max(1);
+
+        if !self.is_terminal {
+            eprintln!("[Equicord Launcher] Step {step}/{total}: {status}");
+            return;
+        }
+
+        let filled = BAR_WIDTH * step.min(total) as usize / total as usize;
+        eprintln!(
+            "\x1b[1m[{}{}] {step}/{total}\x1b[0m {status}",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled)
+        );
+    }
+}
+
+/// Whether `program` can be found on PATH.
+#[cfg(target_os = "linux")]
+fn in_path(program: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+
+    std::env::split_paths(&path).any(|dir| dir.join(program).is_file())
+}
+
+/// A zenity or kdialog progress dialog.
+#[cfg(target_os = "linux")]
+pub enum DialogProgress {
+    /// zenity reads the percentage and `# status` lines from its stdin.
+    Zenity {
+        child: std::sync::Mutex<std::process::Child>,
+        total_steps: u32,
+    },
+    /// kdialog prints the D-Bus service and object path of the dialog, which is then
+    /// controlled through `dbus-send`.
+    Kdialog { service: String, object: String },
+}
+
+#[cfg(target_os = "linux")]
+impl DialogProgress {
+    /// Show a progress dialog, or `None` without a graphical session or dialog tool.
+    pub fn new(title: &str, total_steps: u32) -> Option<Self> {
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return None;
+        }
+
+        let is_kde =
+            std::env::var("XDG_CURRENT_DESKTOP").is_ok_and(|desktop| desktop.contains("KDE"));
+        let kdialog = in_path("kdialog") && in_path("dbus-send");
+
+        if kdialog && (is_kde || !in_path("zenity")) {
+            Self::kdialog(title, total_steps)
+        } else if in_path("zenity") {
+            Self::zenity(title, total_steps)
+        } else {
+            None
+        }
+    }
+
+    fn zenity(title: &str, total_steps: u32) -> Option<Self> {
+        let child = std::process::Command::new("zenity")
+            .args([
+                "--progress",
+                "--title=Equicord Launcher",
+                &format!("--text={title}"),
+                "--percentage=0",
+                "--auto-close",
+                "--no-cancel",
+            ])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .ok()?;
+
+        Some(DialogProgress::Zenity {
+            child: std::sync::Mutex::new(child),
+            total_steps,
+        })
+    }
+
+    fn kdialog(title: &str, total_steps: u32) -> Option<Self> {
+        let output = std::process::Command::new("kdialog")
+            .args([
+                "--title",
+                "Equicord Launcher",
+                "--progressbar",
+                title,
+                &total_steps.to_string(),
+            ])
+            .output()
+            .ok()?;
+
+        let reference = String::from_utf8_lossy(&output.stdout);
+        let mut parts = reference.split_whitespace();
+        let (service, object) = (parts.next()?.to_string(), parts.next()?.to_string());
+
+        Some(DialogProgress::Kdialog { service, object })
+    }
+
+    fn kdialog_call(service: &str, object: &str, method: &str, args: &[&str]) {
+        let _ = std::process::Command::new("dbus-send")
+            .args([
+                "--session",
+                "--type=method_call",
+                &format!("--dest={service}"),
+                object,
+                method,
+            ])
+            .args(args)
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ProgressReporter for DialogProgress {
+    fn update(&self, step: u32, status: &str) {
+        match self {
+            DialogProgress::Zenity { child, total_steps } => {
+                use std::io::Write;
+
+                let Ok(mut child) = child.lock() else {
+                    return;
+                };
+                let Some(stdin) = child.stdin.as_mut() else {
+                    return;
+                };
+
+                // Stop just short of 100%, which would auto-close the dialog
+                let percent = (step * 100 / (*total_steps).max(1)).min(99);
+                let _ = writeln!(stdin, "{percent}\n# {status}");
+            }
+            DialogProgress::Kdialog { service, object } => {
+                Self::kdialog_call(
+                    service,
+                    object,
+                    "org.freedesktop.DBus.Properties.Set",
+                    &[
+                        "string:org.kde.kdialog.ProgressDialog",
+                        "string:value",
+                        &format!("variant:int32:{step}"),
+                    ],
+                );
+                Self::kdialog_call(
+                    service,
+                    object,
+                    "org.kde.kdialog.ProgressDialog.setLabelText",
+                    &[&format!("string:{status}")],
+                );
+            }
+        }
+    }
+
+    fn close(self: Box<Self>) {
+        // Brief pause so user can see the last status
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DialogProgress {
+    fn drop(&mut self) {
+        match self {
+            DialogProgress::Zenity { child, .. } => {
+                if let Ok(child) = child.get_mut() {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            }
+            DialogProgress::Kdialog { service, object } => {
+                Self::kdialog_call(service, object, "org.kde.kdialog.ProgressDialog.close", &[]);
+            }
+        }
+    }
+}
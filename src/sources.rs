@@ -0,0 +1,390 @@
+//! Userplugin sources for custom builds.
+//!
+//! A `--custom` source is either a local directory or a git URL, optionally
+//! suffixed with `#ref` to pin a branch, tag or commit. Git sources are
+//! cloned into a managed directory and updated before every build.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::build_log::BuildLog;
+use crate::builder::silent_cmd;
+use crate::constants;
+use crate::dry_run::Plan;
+use crate::userplugins::to_hex;
+
+pub enum UserpluginSource {
+    Local(PathBuf),
+    Git {
+        url: String,
+        git_ref: Option<String>,
+    },
+}
+
+impl UserpluginSource {
+    /// Parse a `--custom` argument. Existing paths always win over URL detection.
+    pub fn parse(source: &str) -> Self {
+        if Path::new(source).exists() || !is_git_url(source) {
+            return UserpluginSource::Local(PathBuf::from(source));
+        }
+
+        match source.rsplit_once('#') {
+            Some((url, git_ref)) if !git_ref.is_empty() => UserpluginSource::Git {
+                url: url.to_string(),
+                git_ref: Some(git_ref.to_string()),
+            },
+            Some((url, _)) => UserpluginSource::Git {
+                url: url.to_string(),
+                git_ref: None,
+            },
+            None => UserpluginSource::Git {
+                url: source.to_string(),
+                git_ref: None,
+            },
+        }
+    }
+
+    /// Check local sources up front, so typos are reported before anything is fetched.
+    pub fn validate(&self) -> Result<(), String> {
+        let UserpluginSource::Local(path) = self else {
+            return Ok(());
+        };
+
+        if !path.exists() {
+            return Err(format!(
+                "Userplugins directory does not exist: {}",
+                path.display()
+            ));
+        }
+
+        if !path.is_dir() {
+            return Err(format!(
+                "Userplugins path is not a directory: {}",
+                path.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get the local directory for this source, cloning git sources first.
+    /// Existing checkouts are only updated if `update` is set.
+    pub fn resolve(
+        &self,
+        update: bool,
+        git_timeout: Option<Duration>,
+        log: &BuildLog,
+    ) -> Result<PathBuf, String> {
+        match self {
+            UserpluginSource::Local(path) => Ok(path.clone()),
+            UserpluginSource::Git { url, git_ref } => {
+                let checkout_dir = checkout_dir(url, git_ref.as_deref())?;
+
+                if update || !checkout_dir.join(".git").exists() {
+                    update_checkout(&checkout_dir, url, git_ref.as_deref(), git_timeout, log)?;
+                }
+
+                Ok(checkout_dir)
+            }
+        }
+    }
+
+    /// Like [`resolve`](Self::resolve), but only adds the git commands it would run to `plan`.
+    pub fn plan_resolve(&self, update: bool, plan: &mut Plan) -> Result<PathBuf, String> {
+        let (url, git_ref) = match self {
+            UserpluginSource::Local(path) => return Ok(path.clone()),
+            UserpluginSource::Git { url, git_ref } => (url, git_ref),
+        };
+
+        let checkout_dir = checkout_dir(url, git_ref.as_deref())?;
+        let has_checkout = checkout_dir.join(".git").exists();
+        if has_checkout && !update {
+            return Ok(checkout_dir);
+        }
+
+        let mut commands = if has_checkout {
+            vec![vec!["remote", "set-url", "origin", url]]
+        } else {
+            vec![
+                vec!["init", "--quiet"],
+                vec!["remote", "add", "origin", url],
+            ]
+        };
+        commands.push(vec![
+            "fetch",
+            "--depth",
+            "1",
+            "origin",
+            git_ref.as_deref().unwrap_or("HEAD"),
+        ]);
+        commands.push(vec!["reset", "--hard", "FETCH_HEAD"]);
+        commands.push(vec!["clean", "-fd"]);
+
+        for args in commands {
+            plan.run(silent_cmd("git").args(args).current_dir(&checkout_dir));
+        }
+
+        Ok(checkout_dir)
+    }
+}
+
+impl std::fmt::Display for UserpluginSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserpluginSource::Local(path) => write!(f, "{}", path.display()),
+            UserpluginSource::Git { url, git_ref: None } => write!(f, "{url}"),
+            UserpluginSource::Git {
+                url,
+                git_ref: Some(git_ref),
+            } => write!(f, "{url}#{git_ref}"),
+        }
+    }
+}
+
+/// Whether `source` names a remote repository rather than a local path.
+///
+/// A `.git` suffix alone isn't enough, since a mistyped local path could end in it
+/// too. It only counts for scp-like `host:path.git` URLs.
+fn is_git_url(source: &str) -> bool {
+    let source = source.split('#').next().unwrap_or(source);
+
+    if ["https://", "http://", "git://", "ssh://", "file://", "git@"]
+        .iter()
+        .any(|prefix| source.starts_with(prefix))
+    {
+        return true;
+    }
+
+    // A single letter before the colon is a Windows drive, not a host
+    let is_scp_like = source
+        .split_once(':')
+        .is_some_and(|(host, _)| host.len() > 1 && !host.contains(['/', '\\']));
+
+    is_scp_like && source.ends_with(".git")
+}
+
+/// Turn a URL and ref into a stable, filesystem-safe directory name,
+/// e.g. `github.com_me_plugins@dev-1a2b3c4d`.
+///
+/// Sanitizing can map different sources to the same name, e.g. refs `feature/x` and
+/// `feature_x`, so the name ends in a short hash of the exact URL and ref.
+fn checkout_name(url: &str, git_ref: Option<&str>) -> String {
+    let sanitize = |s: &str| -> String {
+        s.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(url.as_bytes());
+    if let Some(git_ref) = git_ref {
+        hasher.update(format!("#{git_ref}").as_bytes());
+    }
+    let hash = to_hex(&hasher.finalize()[..4]);
+
+    let url = url.split_once("://").map(|x| x.1).unwrap_or(url);
+    let url = url.strip_prefix("git@").unwrap_or(url);
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+
+    match git_ref {
+        Some(git_ref) => format!("{}@{}-{hash}", sanitize(url), sanitize(git_ref)),
+        None => format!("{}-{hash}", sanitize(url)),
+    }
+}
+
+/// Where the git source `url` is checked out.
+fn checkout_dir(url: &str, git_ref: Option<&str>) -> Result<PathBuf, String> {
+    let sources_dir = constants::userplugin_sources_dir()
+        .ok_or_else(|| "Failed to determine userplugin sources directory".to_string())?;
+
+    Ok(sources_dir.join(checkout_name(url, git_ref)))
+}
+
+fn run_git(
+    dir: &Path,
+    args: &[&str],
+    timeout: Option<Duration>,
+    log: &BuildLog,
+) -> Result<(), String> {
+    log.run(
+        silent_cmd("git").args(args).current_dir(dir),
+        &format!("git {}", args[0]),
+        timeout,
+    )
+}
+
+/// Fetch `git_ref` (or the remote's default branch) and hard-reset the checkout to it.
+///
+/// If the fetch fails but a previous checkout exists, that checkout is used as-is,
+/// so an offline launch still builds with the last known plugins.
+fn update_checkout(
+    checkout_dir: &Path,
+    url: &str,
+    git_ref: Option<&str>,
+    timeout: Option<Duration>,
+    log: &BuildLog,
+) -> Result<(), String> {
+    let has_checkout = checkout_dir.join(".git").exists();
+
+    if has_checkout {
+        info!("Updating userplugin source {url}...");
+        run_git(
+            checkout_dir,
+            &["remote", "set-url", "origin", url],
+            timeout,
+            log,
+        )?;
+    } else {
+        info!("Cloning userplugin source {url}...");
+        std::fs::create_dir_all(checkout_dir)
+            .map_err(|e| format!("Failed to create directory {:?}: {e}", checkout_dir))?;
+        run_git(checkout_dir, &["init", "--quiet"], timeout, log)?;
+        run_git(
+            checkout_dir,
+            &["remote", "add", "origin", url],
+            timeout,
+            log,
+        )?;
+    }
+
+    let fetch = run_git(
+        checkout_dir,
+        &["fetch", "--depth", "1", "origin", git_ref.unwrap_or("HEAD")],
+        timeout,
+        log,
+    );
+
+    if let Err(e) = fetch {
+        if has_checkout {
+            warn!("{e}");
+            warn!("Using the existing checkout of {url}.");
+            return Ok(());
+        }
+
+        // Don't leave a half-initialized checkout behind, or the next launch
+        // would mistake it for a usable one.
+        let _ = std::fs::remove_dir_all(checkout_dir);
+        return Err(format!("Failed to fetch userplugin source {url}: {e}"));
+    }
+
+    run_git(
+        checkout_dir,
+        &["reset", "--hard", "FETCH_HEAD"],
+        timeout,
+        log,
+    )?;
+    run_git(checkout_dir, &["clean", "-fd"], timeout, log)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_git_urls() {
+        assert!(is_git_url("https://github.com/me/plugins"));
+        assert!(is_git_url("https://github.com/me/plugins.git#dev"));
+        assert!(is_git_url("ssh://git@github.com/me/plugins"));
+        assert!(is_git_url("git@github.com:me/plugins.git"));
+        assert!(is_git_url("file:///srv/git/plugins"));
+        assert!(is_git_url("github.com:me/plugins.git"));
+    }
+
+    #[test]
+    fn keeps_local_paths_local() {
+        assert!(!is_git_url("/home/me/plugins"));
+        assert!(!is_git_url("./plugins"));
+        assert!(!is_git_url("C:\\Users\\me\\plugins"));
+        // Even when they don't exist, which validation reports later
+        assert!(!is_git_url("/home/me/missing-plugins.git"));
+        assert!(!is_git_url("plugins.git"));
+        assert!(!is_git_url("C:\\Users\\me\\plugins.git"));
+        assert!(!is_git_url("C:/Users/me/plugins.git"));
+    }
+
+    #[test]
+    fn parses_refs() {
+        let UserpluginSource::Git { url, git_ref } =
+            UserpluginSource::parse("https://github.com/me/plugins.git#v1.2")
+        else {
+            panic!("expected a git source");
+        };
+        assert_eq!(url, "https://github.com/me/plugins.git");
+        assert_eq!(git_ref.as_deref(), Some("v1.2"));
+
+        let UserpluginSource::Git { url, git_ref } =
+            UserpluginSource::parse("https://github.com/me/plugins#")
+        else {
+            panic!("expected a git source");
+        };
+        assert_eq!(url, "https://github.com/me/plugins");
+        assert_eq!(git_ref, None);
+
+        assert!(matches!(
+            UserpluginSource::parse("/home/me/plugins.git"),
+            UserpluginSource::Local(_)
+        ));
+    }
+
+    /// The readable part of a checkout name, without the hash.
+    fn readable_name(url: &str, git_ref: Option<&str>) -> String {
+        let name = checkout_name(url, git_ref);
+        let (readable, hash) = name.rsplit_once('-').unwrap();
+        assert_eq!(hash.len(), 8);
+        readable.to_string()
+    }
+
+    #[test]
+    fn checkout_names() {
+        assert_eq!(
+            readable_name("https://github.com/me/plugins.git", None),
+            "github.com_me_plugins"
+        );
+        assert_eq!(
+            readable_name("https://github.com/me/plugins/", Some("dev")),
+            "github.com_me_plugins@dev"
+        );
+        assert_eq!(
+            readable_name("git@github.com:me/plugins.git", Some("feature/x")),
+            "github.com_me_plugins@feature_x"
+        );
+        assert_eq!(
+            readable_name("ssh://git@host:2222/me/plugins", None),
+            "host_2222_me_plugins"
+        );
+        assert_eq!(
+            checkout_name("https://github.com/me/plugins", Some("dev")),
+            checkout_name("https://github.com/me/plugins", Some("dev"))
+        );
+    }
+
+    #[test]
+    fn checkout_names_dont_collide() {
+        let url = "https://github.com/me/plugins";
+        assert_ne!(
+            checkout_name(url, Some("feature/x")),
+            checkout_name(url, Some("feature_x"))
+        );
+        assert_ne!(
+            checkout_name("https://host/a_b", None),
+            checkout_name("https://host/a/b", None)
+        );
+        assert_ne!(
+            checkout_name("https://github.com/me/plugins.git", None),
+            checkout_name("https://github.com/me/plugins", None)
+        );
+        assert_ne!(checkout_name(url, None), checkout_name(url, Some("")));
+    }
+}
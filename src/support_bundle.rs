@@ -0,0 +1,276 @@
+//! Support bundles: a single zip with everything we usually ask for in an issue.
+//!
+//! Contains the launcher's settings and config file, the release and build state files, the logs,
+//! the `doctor` output and where the Discord installs are. Paths in the user's home
+//! directory are written as `~` so the bundle doesn't leak their user name.
+
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::build_log::{self, UtcTime};
+use crate::discord::{self, DiscordBranch, DiscordPath};
+use crate::zip::ZipWriter;
+use crate::{constants, doctor};
+
+/// State files from the asset cache worth including.
+const STATE_FILES: &[&str] = &[
+    constants::RELEASE_INFO_FILE,
+    constants::OPEN_ASAR_RELEASE_INFO_FILE,
+    constants::CUSTOM_BUILD_STATE_FILE,
+];
+
+/// Replace the home directory in `text` with `~`, including JSON-escaped Windows paths.
+fn redact(text: &str, home: Option<&Path>) -> String {
+    let Some(home) = home.map(|home| home.to_string_lossy().to_string()) else {
+        return text.to_string();
+    };
+    if home.len() <= 1 {
+        return text.to_string();
+    }
+
+    let mut text = text.to_string();
+    for variant in [home.replace('\\', "\\\\"), home.replace('\\', "/"), home] {
+        text = replace_path(&text, &variant);
+    }
+    text
+}
+
+/// Replace `path` in `text` with `~`, unless it's only the start of a longer name.
+fn replace_path(text: &str, path: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(index) = rest.find(path) {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + path.len()..];
+
+        // `/home/me` must not turn `/home/megan` into `~gan`
+        if rest.starts_with(|c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            result.push_str(path);
+        } else {
+            result.push('~');
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn or_unknown(path: Option<PathBuf>) -> String {
+    path.map(|path| path.display().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn launcher_info(branch: DiscordBranch) -> String {
+    let mut info = format!(
+        "Equicord Launcher {}\n\
+        Created: {}\n\
+        OS: {} {}\n\
+        Executable: {}\n\
+        Branch: {}\n\
+        Library: {}\n\
+        Cache directory: {}\n\
+        State directory: {}\n\
+        Config file: {}\n\
+        Data directory override: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        build_log::utc_timestamp(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        or_unknown(std::env::current_exe().ok()),
+        branch.display_name(),
+        constants::get_library_path(),
+        or_unknown(constants::cache_root()),
+        or_unknown(constants::state_root()),
+        or_unknown(constants::config_file()),
+        constants::data_dir_override()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|| "none".to_string()),
+    );
+
+    if let Ok(home) = std::env::var(constants::DATA_DIR_ENV) {
+        info.push_str(&format!("{}: {home}\n", constants::DATA_DIR_ENV));
+    }
+
+    info
+}
+
+/// The version of the Discord install at `executable`.
+fn discord_version(executable: &Path) -> Option<String> {
+    let install_dir = executable.parent()?;
+
+    let build_info = std::fs::read_to_string(install_dir.join("resources/build_info.json"))
+        .ok()
+        .and_then(|data| data.parse::<tinyjson::JsonValue>().ok())
+        .and_then(|json| {
+            let object: &std::collections::HashMap<_, _> = json.get()?;
+            object.get("version")?.get::<String>().cloned()
+        });
+
+    // Windows installs live in versioned `app-1.0.9000` directories
+    build_info.or_else(|| {
+        install_dir
+            .file_name()?
+            .to_str()?
+            .strip_prefix("app-")
+            .map(str::to_string)
+    })
+}
+
+fn discord_info() -> String {
+    let mut info = String::new();
+
+    for branch in DiscordBranch::ALL {
+        info.push_str(&format!("{}:\n", branch.display_name()));
+
+        match discord::find_discord(branch) {
+            Err(e) => info.push_str(&format!("  {e}\n")),
+            Ok(None) => info.push_str("  not found\n"),
+            Ok(Some(DiscordPath::Filesystem(executable))) => {
+                info.push_str(&format!("  Executable: {}\n", executable.display()));
+                info.push_str(&format!(
+                    "  Version: {}\n",
+                    discord_version(&executable).unwrap_or_else(|| "unknown".to_string())
+                ));
+            }
+            Ok(Some(DiscordPath::FlatpakId(id))) => {
+                let installation = match id {
+                    electron_hook::FlatpakID::User(_) => "--user",
+                    electron_hook::FlatpakID::System(_) => "--system",
+                };
+                info.push_str(&format!("  Flatpak: {id} ({installation})\n"));
+
+                if let Ok(output) = std::process::Command::new("flatpak")
+                    .args(["info", installation, &id.to_string()])
+                    .output()
+                {
+                    for line in String::from_utf8_lossy(&output.stdout).lines() {
+                        if !line.trim().is_empty() {
+                            info.push_str(&format!("  {}\n", line.trim()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    info
+}
+
+/// Create a support bundle at `output`, or in the current directory if not given.
+///
+/// Returns the path of the bundle.
+pub fn create(branch: DiscordBranch, output: Option<PathBuf>) -> Result<PathBuf, String> {
+    let now = UtcTime::now();
+    let output = output.unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "equicord-support-{:04}{:02}{:02}-{:02}{:02}{:02}.zip",
+            now.year, now.month, now.day, now.hour, now.minute, now.second
+        ))
+    });
+
+    info!("Running diagnostics...");
+    let doctor_report = doctor::format_checks(&doctor::run_checks(branch));
+
+    let home = dirs::home_dir();
+    let mut files = vec![
+        ("launcher.txt".to_string(), launcher_info(branch)),
+        ("doctor.txt".to_string(), doctor_report),
+        ("discord.txt".to_string(), discord_info()),
+    ];
+
+    if let Some(Ok(data)) = constants::config_file().map(std::fs::read) {
+        files.push((
+            constants::CONFIG_FILE.to_string(),
+            String::from_utf8_lossy(&data).to_string(),
+        ));
+    }
+
+    if let Some(assets_dir) = constants::asset_cache_dir() {
+        for file in STATE_FILES {
+            if let Ok(data) = std::fs::read(assets_dir.join(file)) {
+                files.push((
+                    format!("state/{file}"),
+                    String::from_utf8_lossy(&data).to_string(),
+                ));
+            }
+        }
+    }
+
+    if let Some(Ok(entries)) = constants::logs_dir().map(std::fs::read_dir) {
+        let mut logs: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect();
+        logs.sort();
+
+        for log in logs {
+            if let (Some(name), Ok(data)) = (log.file_name(), std::fs::read(&log)) {
+                files.push((
+                    format!("logs/{}", name.to_string_lossy()),
+                    String::from_utf8_lossy(&data).to_string(),
+                ));
+            }
+        }
+    }
+
+    let file = std::fs::File::create(&output)
+        .map_err(|e| format!("Failed to create {:?}: {e}", output))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file), now);
+
+    for (name, contents) in &files {
+        zip.add(name, redact(contents, home.as_deref()).as_bytes())
+            .map_err(|e| format!("Failed to write {:?}: {e}", output))?;
+    }
+    zip.finish()
+        .map_err(|e| format!("Failed to write {:?}: {e}", output))?;
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_unix_home() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            redact(
+                "Log: /home/me/.local/state/equicord/logs\nHome: /home/me",
+                Some(home)
+            ),
+            "Log: ~/.local/state/equicord/logs\nHome: ~"
+        );
+    }
+
+    #[test]
+    fn redacts_windows_home_in_every_spelling() {
+        let home = Path::new("C:\\Users\\me");
+        assert_eq!(
+            redact(
+                r#"C:\Users\me\AppData {"path":"C:\\Users\\me\\x"} C:/Users/me/y"#,
+                Some(home)
+            ),
+            r#"~\AppData {"path":"~\\x"} ~/y"#
+        );
+    }
+
+    #[test]
+    fn keeps_longer_names_sharing_the_prefix() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            redact("/home/megan/x /home/me.old /home/me/x", Some(home)),
+            "/home/megan/x /home/me.old ~/x"
+        );
+    }
+
+    #[test]
+    fn leaves_text_alone_without_a_usable_home() {
+        assert_eq!(redact("/home/me/x", None), "/home/me/x");
+        assert_eq!(redact("/home/me/x", Some(Path::new("/"))), "/home/me/x");
+    }
+}
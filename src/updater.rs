@@ -1,25 +1,50 @@
 use std::collections::HashMap;
+
+use log::{debug, info, warn};
 use tinyjson::JsonValue;
 use tokio::task::JoinSet;
 
 use crate::constants;
+use crate::events::{self, Event};
 
-static USER_AGENT: &str = concat!("EquicordLauncher/", env!("CARGO_PKG_VERSION"));
+pub(crate) static USER_AGENT: &str = concat!("EquicordLauncher/", env!("CARGO_PKG_VERSION"));
 
+/// The part of a cached release info file needed to tell whether it's outdated.
 struct GithubRelease {
+    updated_at: String,
+}
+
+/// A newer release found by [`check_assets`] or [`check_open_asar`], not downloaded yet.
+pub struct PendingUpdate {
     pub tag_name: String,
-    pub name: String,
-    pub updated_at: String,
+    name: String,
+    updated_at: String,
+    /// Names of the files in the asset cache to download, with their URLs.
+    pub files: Vec<(String, String)>,
 }
 
-struct GithubReleaseAsset {
-    pub name: String,
-    pub browser_download_url: String,
+/// Report an update check of `target` that ended early, i.e. failed.
+fn report_failure(target: &str, result: Option<()>) -> Option<()> {
+    if result.is_none() {
+        events::emit(Event::UpdateCheck {
+            target,
+            status: "failed",
+            version: None,
+        });
+    }
+
+    result
 }
 
 pub async fn download_assets() -> Option<()> {
-    let assets_dir = constants::asset_cache_dir().unwrap();
-    let release_file = assets_dir.join(constants::RELEASE_INFO_FILE);
+    report_failure("equicord", update_assets().await)
+}
+
+/// Check GitHub for a newer Equicord release than the cached one, without downloading it.
+///
+/// Returns `Some(None)` if the cache is up to date and `None` if the check failed.
+pub async fn check_assets() -> Option<Option<PendingUpdate>> {
+    let release_file = constants::asset_cache_path()?.join(constants::RELEASE_INFO_FILE);
 
     // Get the current release.json if it exists.
     let current_version = if release_file.exists() {
@@ -28,28 +53,22 @@ pub async fn download_assets() -> Option<()> {
                 let json: JsonValue = match data.parse() {
                     Ok(j) => j,
                     Err(e) => {
-                        eprintln!("[Equicord Launcher] Failed to parse release.json: {e:?}");
+                        warn!("Failed to parse release.json: {e:?}");
                         return None;
                     }
                 };
                 let object: &HashMap<_, _> = json.get()?;
 
-                let tag_name: &String = object.get("tag_name")?.get()?;
-                let name: &String = object.get("name")?.get()?;
                 let updated_at: String = object
                     .get("updated_at")
                     .and_then(|v| v.get::<String>())
                     .cloned()
                     .unwrap_or_default();
 
-                Some(GithubRelease {
-                    tag_name: tag_name.clone(),
-                    name: name.clone(),
-                    updated_at,
-                })
+                Some(GithubRelease { updated_at })
             }
             Err(e) => {
-                eprintln!("[Equicord Launcher] Failed to read release.json: {e}");
+                warn!("Failed to read release.json: {e}");
                 None
             }
         }
@@ -58,7 +77,12 @@ pub async fn download_assets() -> Option<()> {
     };
 
     // Get the latest release manifest from GitHub.
-    println!("[Equicord Launcher] Checking for updates...");
+    info!("Checking for updates...");
+    events::emit(Event::UpdateCheck {
+        target: "equicord",
+        status: "checking",
+        version: None,
+    });
     let response = ureq::get(constants::RELEASE_URL)
         .header("User-Agent", USER_AGENT)
         .call();
@@ -66,24 +90,24 @@ pub async fn download_assets() -> Option<()> {
     let mut response = match response {
         Ok(resp) => resp,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to fetch release info from GitHub: {e}");
-            eprintln!("[Equicord Launcher] This may be due to rate limiting (60 requests/hour for unauthenticated requests).");
+            warn!("Failed to fetch release info from GitHub: {e}");
+            warn!(
+                "This may be due to rate limiting (60 requests/hour for unauthenticated requests)."
+            );
             return None;
         }
     };
 
     let status = response.status();
     if status != 200 {
-        eprintln!(
-            "[Equicord Launcher] GitHub API returned non-200 status: {status} - updates may be rate-limited."
-        );
+        warn!("GitHub API returned non-200 status: {status} - updates may be rate-limited.");
         return None;
     }
 
     let body = match response.body_mut().read_to_string() {
         Ok(b) => b,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to read response body: {e}");
+            warn!("Failed to read response body: {e}");
             return None;
         }
     };
@@ -91,7 +115,7 @@ pub async fn download_assets() -> Option<()> {
     let json: JsonValue = match body.parse() {
         Ok(j) => j,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to parse GitHub API response: {e:?}");
+            warn!("Failed to parse GitHub API response: {e:?}");
             return None;
         }
     };
@@ -105,20 +129,30 @@ pub async fn download_assets() -> Option<()> {
     // We use updated_at instead of tag_name/name because Equicord uses a rolling "latest" tag.
     if let Some(release) = current_version {
         if release.updated_at == *updated_at {
-            println!("[Equicord Launcher] Already up to date (updated_at: {updated_at}).");
-            return Some(());
+            info!("Already up to date (updated_at: {updated_at}).");
+            events::emit(Event::UpdateCheck {
+                target: "equicord",
+                status: "up_to_date",
+                version: Some(tag_name),
+            });
+            return Some(None);
         }
-        println!(
-            "[Equicord Launcher] Update detected: cached updated_at='{}' vs remote updated_at='{updated_at}'",
+        info!(
+            "Update detected: cached updated_at='{}' vs remote updated_at='{updated_at}'",
             release.updated_at
         );
     }
 
-    println!("[Equicord Launcher] An update is available... Downloading...");
+    info!("An update is available.");
+    events::emit(Event::UpdateCheck {
+        target: "equicord",
+        status: "update_available",
+        version: Some(tag_name),
+    });
 
     // Loop over the assets and find the ones we want.
     let assets: &Vec<_> = object.get("assets")?.get()?;
-    let assets: Vec<_> = assets
+    let files: Vec<_> = assets
         .iter()
         .filter_map(|asset| {
             let asset: &HashMap<_, _> = asset.get()?;
@@ -126,43 +160,53 @@ pub async fn download_assets() -> Option<()> {
             let name: &String = asset.get("name")?.get()?;
             let browser_download_url: &String = asset.get("browser_download_url")?.get()?;
             if constants::RELEASE_ASSETS.contains(&name.as_str()) {
-                Some(GithubReleaseAsset {
-                    name: name.clone(),
-                    browser_download_url: browser_download_url.clone(),
-                })
+                Some((name.clone(), browser_download_url.clone()))
             } else {
                 None
             }
         })
         .collect();
 
-    if assets.is_empty() {
-        eprintln!("[Equicord Launcher] No matching release assets found in the GitHub release.");
+    if files.is_empty() {
+        warn!("No matching release assets found in the GitHub release.");
         return None;
     }
 
-    println!(
-        "[Equicord Launcher] Downloading {} assets...",
-        assets.len()
-    );
+    Some(Some(PendingUpdate {
+        tag_name: tag_name.clone(),
+        name: name.clone(),
+        updated_at: updated_at.clone(),
+        files,
+    }))
+}
+
+async fn update_assets() -> Option<()> {
+    let Some(PendingUpdate {
+        tag_name,
+        name,
+        updated_at,
+        files,
+    }) = check_assets().await?
+    else {
+        return Some(());
+    };
+
+    let assets_dir = constants::asset_cache_dir().unwrap();
+    let release_file = assets_dir.join(constants::RELEASE_INFO_FILE);
+
+    info!("Downloading {} assets...", files.len());
 
     // Spawn all the download tasks simultaneously.
     let mut tasks = JoinSet::new();
-    for asset in assets {
-        let url = asset.browser_download_url.clone();
-        let asset_name = asset.name.clone();
-
+    for (asset_name, url) in files {
         tasks.spawn(async move {
-            let response = ureq::get(&url)
-                .header("User-Agent", USER_AGENT)
-                .call();
+            debug!("Downloading {url}");
+            let response = ureq::get(&url).header("User-Agent", USER_AGENT).call();
 
             let mut response = match response {
                 Ok(resp) => resp,
                 Err(e) => {
-                    eprintln!(
-                        "[Equicord Launcher] Failed to download asset '{asset_name}': {e}"
-                    );
+                    warn!("Failed to download asset '{asset_name}': {e}");
                     return None;
                 }
             };
@@ -170,26 +214,35 @@ pub async fn download_assets() -> Option<()> {
             let body = match response.body_mut().read_to_vec() {
                 Ok(b) => b,
                 Err(e) => {
-                    eprintln!(
-                        "[Equicord Launcher] Failed to read asset '{asset_name}': {e}"
-                    );
+                    warn!("Failed to read asset '{asset_name}': {e}");
                     return None;
                 }
             };
 
-            println!("[Equicord Launcher] Downloaded '{asset_name}' ({} bytes)", body.len());
-            Some((asset.name, body))
+            info!("Downloaded '{asset_name}' ({} bytes)", body.len());
+            Some((asset_name, body))
         });
     }
 
     // Wait for each task to finish and write them to disk.
+    let total = tasks.len();
+    let mut completed = 0;
     let mut all_succeeded = true;
     while let Some(resp) = tasks.join_next().await {
         match resp {
             Ok(Some((name, body))) => {
+                completed += 1;
+                events::emit(Event::Download {
+                    target: "equicord",
+                    name: &name,
+                    bytes: body.len(),
+                    completed,
+                    total,
+                });
+
                 let path = assets_dir.join(&name);
                 if let Err(e) = std::fs::write(&path, body) {
-                    eprintln!("[Equicord Launcher] Failed to write asset '{name}' to disk: {e}");
+                    warn!("Failed to write asset '{name}' to disk: {e}");
                     all_succeeded = false;
                 }
             }
@@ -197,14 +250,14 @@ pub async fn download_assets() -> Option<()> {
                 all_succeeded = false;
             }
             Err(e) => {
-                eprintln!("[Equicord Launcher] Asset download task panicked: {e}");
+                warn!("Asset download task panicked: {e}");
                 all_succeeded = false;
             }
         }
     }
 
     if !all_succeeded {
-        eprintln!("[Equicord Launcher] Some assets failed to download. Update may be incomplete.");
+        warn!("Some assets failed to download. Update may be incomplete.");
         // Still write the release info so we don't re-download the successful ones,
         // but return None to signal the failure.
     }
@@ -219,12 +272,17 @@ pub async fn download_assets() -> Option<()> {
     );
 
     if let Err(e) = std::fs::write(&release_file, release_json) {
-        eprintln!("[Equicord Launcher] Failed to write release.json: {e}");
+        warn!("Failed to write release.json: {e}");
         return None;
     }
 
     if all_succeeded {
-        println!("[Equicord Launcher] Update complete.");
+        info!("Update complete.");
+        events::emit(Event::UpdateCheck {
+            target: "equicord",
+            status: "updated",
+            version: Some(&tag_name),
+        });
         Some(())
     } else {
         None
@@ -232,7 +290,12 @@ pub async fn download_assets() -> Option<()> {
 }
 
 pub async fn download_open_asar() -> Option<()> {
-    let assets_dir = constants::asset_cache_dir().unwrap();
+    report_failure("openasar", update_open_asar().await)
+}
+
+/// Check GitHub for a newer OpenAsar release than the cached one, like [`check_assets`].
+pub async fn check_open_asar() -> Option<Option<PendingUpdate>> {
+    let assets_dir = constants::asset_cache_path()?;
     let open_asar_path = assets_dir.join(constants::OPEN_ASAR_FILENAME);
     let release_file = assets_dir.join(constants::OPEN_ASAR_RELEASE_INFO_FILE);
 
@@ -243,30 +306,22 @@ pub async fn download_open_asar() -> Option<()> {
                 let json: JsonValue = match data.parse() {
                     Ok(j) => j,
                     Err(e) => {
-                        eprintln!(
-                            "[Equicord Launcher] Failed to parse open_asar_release.json: {e:?}"
-                        );
+                        warn!("Failed to parse open_asar_release.json: {e:?}");
                         return None;
                     }
                 };
                 let object: &HashMap<_, _> = json.get()?;
 
-                let tag_name: &String = object.get("tag_name")?.get()?;
-                let name: &String = object.get("name")?.get()?;
                 let updated_at: String = object
                     .get("updated_at")
                     .and_then(|v| v.get::<String>())
                     .cloned()
                     .unwrap_or_default();
 
-                Some(GithubRelease {
-                    tag_name: tag_name.clone(),
-                    name: name.clone(),
-                    updated_at,
-                })
+                Some(GithubRelease { updated_at })
             }
             Err(e) => {
-                eprintln!("[Equicord Launcher] Failed to read open_asar_release.json: {e}");
+                warn!("Failed to read open_asar_release.json: {e}");
                 None
             }
         }
@@ -274,7 +329,12 @@ pub async fn download_open_asar() -> Option<()> {
         None
     };
 
-    println!("[Equicord Launcher] Checking for OpenAsar updates...");
+    info!("Checking for OpenAsar updates...");
+    events::emit(Event::UpdateCheck {
+        target: "openasar",
+        status: "checking",
+        version: None,
+    });
 
     let response = ureq::get(constants::OPEN_ASAR_URL)
         .header("User-Agent", USER_AGENT)
@@ -283,24 +343,24 @@ pub async fn download_open_asar() -> Option<()> {
     let mut response = match response {
         Ok(resp) => resp,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to fetch OpenAsar release info: {e}");
-            eprintln!("[Equicord Launcher] This may be due to rate limiting (60 requests/hour for unauthenticated requests).");
+            warn!("Failed to fetch OpenAsar release info: {e}");
+            warn!(
+                "This may be due to rate limiting (60 requests/hour for unauthenticated requests)."
+            );
             return None;
         }
     };
 
     let status = response.status();
     if status != 200 {
-        eprintln!(
-            "[Equicord Launcher] GitHub API returned non-200 status for OpenAsar: {status}"
-        );
+        warn!("GitHub API returned non-200 status for OpenAsar: {status}");
         return None;
     }
 
     let body = match response.body_mut().read_to_string() {
         Ok(b) => b,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to read OpenAsar response body: {e}");
+            warn!("Failed to read OpenAsar response body: {e}");
             return None;
         }
     };
@@ -308,7 +368,7 @@ pub async fn download_open_asar() -> Option<()> {
     let json: JsonValue = match body.parse() {
         Ok(j) => j,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to parse OpenAsar API response: {e:?}");
+            warn!("Failed to parse OpenAsar API response: {e:?}");
             return None;
         }
     };
@@ -322,12 +382,22 @@ pub async fn download_open_asar() -> Option<()> {
     if let Some(release) = current_version {
         // If file also exists (double check), then return
         if release.updated_at == *updated_at && open_asar_path.exists() {
-            println!("[Equicord Launcher] OpenAsar already up to date.");
-            return Some(());
+            info!("OpenAsar already up to date.");
+            events::emit(Event::UpdateCheck {
+                target: "openasar",
+                status: "up_to_date",
+                version: Some(tag_name),
+            });
+            return Some(None);
         }
     }
 
-    println!("[Equicord Launcher] OpenAsar update available... Downloading...");
+    info!("OpenAsar update available.");
+    events::emit(Event::UpdateCheck {
+        target: "openasar",
+        status: "update_available",
+        version: Some(tag_name),
+    });
 
     let assets: &Vec<_> = object.get("assets")?.get()?;
     // OpenAsar releases usually have "app.asar"
@@ -343,10 +413,36 @@ pub async fn download_open_asar() -> Option<()> {
     });
 
     let Some(asset_url) = asset else {
-        eprintln!("[Equicord Launcher] Could not find 'app.asar' asset in OpenAsar release.");
+        warn!("Could not find 'app.asar' asset in OpenAsar release.");
         return None;
     };
 
+    Some(Some(PendingUpdate {
+        tag_name: tag_name.clone(),
+        name: name.clone(),
+        updated_at: updated_at.clone(),
+        files: vec![(constants::OPEN_ASAR_FILENAME.to_string(), asset_url)],
+    }))
+}
+
+async fn update_open_asar() -> Option<()> {
+    let Some(PendingUpdate {
+        tag_name,
+        name,
+        updated_at,
+        files,
+    }) = check_open_asar().await?
+    else {
+        return Some(());
+    };
+    let (_, asset_url) = files.into_iter().next()?;
+
+    let assets_dir = constants::asset_cache_dir().unwrap();
+    let open_asar_path = assets_dir.join(constants::OPEN_ASAR_FILENAME);
+    let release_file = assets_dir.join(constants::OPEN_ASAR_RELEASE_INFO_FILE);
+
+    info!("Downloading OpenAsar...");
+    debug!("Downloading {asset_url}");
     let response = ureq::get(&asset_url)
         .header("User-Agent", USER_AGENT)
         .call();
@@ -354,7 +450,7 @@ pub async fn download_open_asar() -> Option<()> {
     let mut response = match response {
         Ok(resp) => resp,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to download OpenAsar: {e}");
+            warn!("Failed to download OpenAsar: {e}");
             return None;
         }
     };
@@ -362,18 +458,22 @@ pub async fn download_open_asar() -> Option<()> {
     let body = match response.body_mut().read_to_vec() {
         Ok(b) => b,
         Err(e) => {
-            eprintln!("[Equicord Launcher] Failed to read OpenAsar download: {e}");
+            warn!("Failed to read OpenAsar download: {e}");
             return None;
         }
     };
 
-    println!(
-        "[Equicord Launcher] Downloaded OpenAsar ({} bytes)",
-        body.len()
-    );
+    info!("Downloaded OpenAsar ({} bytes)", body.len());
+    events::emit(Event::Download {
+        target: "openasar",
+        name: constants::OPEN_ASAR_FILENAME,
+        bytes: body.len(),
+        completed: 1,
+        total: 1,
+    });
 
     if let Err(e) = std::fs::write(&open_asar_path, body) {
-        eprintln!("[Equicord Launcher] Failed to write OpenAsar to disk: {e}");
+        warn!("Failed to write OpenAsar to disk: {e}");
         return None;
     }
 
@@ -387,10 +487,15 @@ pub async fn download_open_asar() -> Option<()> {
     );
 
     if let Err(e) = std::fs::write(&release_file, release_json) {
-        eprintln!("[Equicord Launcher] Failed to write open_asar_release.json: {e}");
+        warn!("Failed to write open_asar_release.json: {e}");
         return None;
     }
 
-    println!("[Equicord Launcher] OpenAsar update complete.");
+    info!("OpenAsar update complete.");
+    events::emit(Event::UpdateCheck {
+        target: "openasar",
+        status: "updated",
+        version: Some(&tag_name),
+    });
     Some(())
 }
@@ -0,0 +1,888 @@
+//! Hashing and syncing of userplugin directories for custom builds.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use tinyjson::JsonValue;
+
+use crate::constants;
+
+/// Files modified this recently are always rehashed, because a second write within the
+/// filesystem's timestamp granularity would leave both size and mtime unchanged.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// A file's content hash, valid for as long as its size and timestamps (the stamp) are unchanged.
+struct CachedHash {
+    stamp: String,
+    hash: String,
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Load the per-file hash cache from disk. A missing or corrupt cache is simply empty.
+fn load_hash_cache(cache_dir: &Path) -> HashMap<String, CachedHash> {
+    let cache_file = cache_dir.join(constants::USERPLUGINS_HASH_CACHE_FILE);
+
+    let Some(json) = std::fs::read_to_string(cache_file)
+        .ok()
+        .and_then(|data| data.parse::<JsonValue>().ok())
+    else {
+        return HashMap::new();
+    };
+
+    let Some(object) = json.get::<HashMap<String, JsonValue>>() else {
+        return HashMap::new();
+    };
+
+    object
+        .iter()
+        .filter_map(|(path, entry)| {
+            let entry: &HashMap<_, _> = entry.get()?;
+            let stamp: &String = entry.get("stamp")?.get()?;
+            let hash: &String = entry.get("hash")?.get()?;
+
+            Some((
+                path.clone(),
+                CachedHash {
+                    stamp: stamp.clone(),
+                    hash: hash.clone(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Save the per-file hash cache to disk.
+fn save_hash_cache(cache_dir: &Path, cache: &HashMap<String, CachedHash>) -> Result<(), String> {
+    let cache_file = cache_dir.join(constants::USERPLUGINS_HASH_CACHE_FILE);
+
+    let object: HashMap<String, JsonValue> = cache
+        .iter()
+        .map(|(path, entry)| {
+            let entry = HashMap::from([
+                ("stamp".to_string(), JsonValue::String(entry.stamp.clone())),
+                ("hash".to_string(), JsonValue::String(entry.hash.clone())),
+            ]);
+            (path.clone(), JsonValue::Object(entry))
+        })
+        .collect();
+
+    let json = JsonValue::Object(object)
+        .stringify()
+        .map_err(|e| format!("Failed to serialize hash cache: {e:?}"))?;
+
+    std::fs::write(cache_file, json).map_err(|e| format!("Failed to write hash cache: {e}"))
+}
+
+/// Hash a file's contents, reusing the cached hash if its stamp is unchanged.
+fn hash_file(
+    path: &Path,
+    old_cache: &HashMap<String, CachedHash>,
+    new_cache: &mut HashMap<String, CachedHash>,
+) -> Result<String, String> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| format!("Failed to read metadata of {:?}: {e}", path))?;
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    #[allow(unused_mut)]
+    let mut stamp = format!(
+        "{}:{}.{:09}",
+        metadata.len(),
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    );
+
+    // The inode change time can't be set from userspace, so it also catches
+    // tools that restore the mtime after writing
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        stamp.push_str(&format!(
+            ":{}.{:09}",
+            metadata.ctime(),
+            metadata.ctime_nsec()
+        ));
+    }
+
+    let key = path.to_string_lossy().to_string();
+    let is_racy = SystemTime::now()
+        .duration_since(modified)
+        .map(|age| age < RACY_WINDOW)
+        .unwrap_or(true);
+
+    if let Some(cached) = old_cache.get(&key) {
+        if cached.stamp == stamp && !is_racy {
+            let hash = cached.hash.clone();
+            new_cache.insert(
+                key,
+                CachedHash {
+                    stamp,
+                    hash: hash.clone(),
+                },
+            );
+            return Ok(hash);
+        }
+    }
+
+    let mut file =
+        std::fs::File::open(path).map_err(|e| format!("Failed to open {:?}: {e}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to read {:?}: {e}", path))?;
+    let hash = to_hex(&hasher.finalize());
+
+    if !is_racy {
+        new_cache.insert(
+            key,
+            CachedHash {
+                stamp,
+                hash: hash.clone(),
+            },
+        );
+    }
+
+    Ok(hash)
+}
+
+/// Ignore rules for a userplugins directory: the built-in defaults plus its `.equicordignore`.
+pub struct IgnoreRules {
+    matcher: Gitignore,
+    /// The raw rules, folded into the userplugins hash so changing them triggers a rebuild.
+    source: String,
+}
+
+impl IgnoreRules {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut source = String::new();
+
+        for pattern in constants::DEFAULT_USERPLUGIN_IGNORES {
+            builder
+                .add_line(None, pattern)
+                .map_err(|e| format!("Invalid default ignore pattern '{pattern}': {e}"))?;
+            source.push_str(pattern);
+            source.push('\n');
+        }
+
+        let ignore_file = dir.join(constants::USERPLUGIN_IGNORE_FILE);
+        if ignore_file.is_file() {
+            let contents = std::fs::read_to_string(&ignore_file)
+                .map_err(|e| format!("Failed to read {:?}: {e}", ignore_file))?;
+
+            for line in contents.lines() {
+                builder
+                    .add_line(Some(ignore_file.clone()), line)
+                    .map_err(|e| format!("Invalid pattern in {:?}: {e}", ignore_file))?;
+            }
+            source.push_str(&contents);
+        }
+
+        let matcher = builder
+            .build()
+            .map_err(|e| format!("Failed to build ignore rules for {:?}: {e}", dir))?;
+
+        Ok(IgnoreRules { matcher, source })
+    }
+
+    /// Rules that match nothing, for walking directories we manage ourselves.
+    fn none() -> Self {
+        IgnoreRules {
+            matcher: Gitignore::empty(),
+            source: String::new(),
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+/// Compute a SHA-256 over the relative path and contents of every file in the userplugins directories,
+/// along with the ignore rules that decided which files those are.
+///
+/// Per-file hashes are cached in the asset cache keyed by size and timestamps, so only
+/// files that changed since the last launch are actually read.
+pub fn hash_directories(dirs: &[PathBuf], cache_dir: &Path) -> Result<String, String> {
+    let (hash, new_cache) = hash_directories_cached(dirs, cache_dir)?;
+
+    // The cache only speeds things up, so failing to write it isn't fatal
+    if let Some(new_cache) = new_cache {
+        if let Err(e) = save_hash_cache(cache_dir, &new_cache) {
+            warn!("{e}");
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Like [`hash_directories`], but leaves the hash cache as it is, for `--dry-run`.
+pub fn peek_directories_hash(dirs: &[PathBuf], cache_dir: &Path) -> Result<String, String> {
+    hash_directories_cached(dirs, cache_dir).map(|(hash, _)| hash)
+}
+
+/// Hash the directories, returning the updated hash cache unless there was nothing to hash.
+fn hash_directories_cached(
+    dirs: &[PathBuf],
+    cache_dir: &Path,
+) -> Result<(String, Option<HashMap<String, CachedHash>>), String> {
+    let old_cache = load_hash_cache(cache_dir);
+    let mut new_cache = HashMap::new();
+
+    let mut entries = Vec::new();
+    for (index, dir) in dirs.iter().enumerate() {
+        if !dir.exists() {
+            continue;
+        }
+
+        let rules = IgnoreRules::load(dir)?;
+        let mut rules_hasher = Sha256::new();
+        rules_hasher.update(rules.source.as_bytes());
+        entries.push(format!("{index}\0{}", to_hex(&rules_hasher.finalize())));
+
+        let mut files = Vec::new();
+        collect_files(dir, dir, &rules, &mut files)?;
+
+        for relative in files {
+            let hash = hash_file(&dir.join(&relative), &old_cache, &mut new_cache)?;
+            // Prefix with the source index so moving a plugin between sources counts as a change
+            entries.push(format!("{index}/{}\0{hash}", relative.replace('\\', "/")));
+        }
+    }
+
+    if entries.is_empty() {
+        return Ok(("empty".to_string(), None));
+    }
+
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for entry in &entries {
+        hasher.update(entry.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    Ok((to_hex(&hasher.finalize()), Some(new_cache)))
+}
+
+/// Collect the paths of all files under `dir` not excluded by `rules`, relative to `base`.
+fn collect_files(
+    base: &Path,
+    dir: &Path,
+    rules: &IgnoreRules,
+    files: &mut Vec<String>,
+) -> Result<(), String> {
+    let read_dir =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read directory {:?}: {e}", dir))?;
+
+    for entry in read_dir {
+        let entry = entry.map_err(|e| format!("Failed to read dir entry: {e}"))?;
+        let path = entry.path();
+        let is_dir = path.is_dir();
+
+        if rules.is_ignored(&path, is_dir) {
+            continue;
+        }
+
+        if is_dir {
+            collect_files(base, &path, rules, files)?;
+        } else {
+            let relative = path
+                .strip_prefix(base)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+/// Which plugins (top-level entries of `src/userplugins/`) a sync touched.
+#[derive(Default)]
+pub struct SyncReport {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    /// Plugins left out of the build because they failed validation, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+impl SyncReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The plugin a relative path belongs to, i.e. its first component.
+fn plugin_name(relative: &str) -> String {
+    relative
+        .split(['/', '\\'])
+        .next()
+        .unwrap_or(relative)
+        .to_string()
+}
+
+/// Whether two files have the same contents.
+pub(crate) fn files_equal(a: &Path, b: &Path) -> Result<bool, String> {
+    let a_len = std::fs::metadata(a)
+        .map_err(|e| format!("Failed to read metadata of {:?}: {e}", a))?
+        .len();
+    let b_len = std::fs::metadata(b)
+        .map_err(|e| format!("Failed to read metadata of {:?}: {e}", b))?
+        .len();
+
+    if a_len != b_len {
+        return Ok(false);
+    }
+
+    let a = std::fs::read(a).map_err(|e| format!("Failed to read {:?}: {e}", a))?;
+    let b = std::fs::read(b).map_err(|e| format!("Failed to read {:?}: {e}", b))?;
+    Ok(a == b)
+}
+
+/// Remove empty directories under `dir` (but not `dir` itself), deepest first.
+fn remove_empty_dirs(dir: &Path) -> Result<(), String> {
+    let read_dir =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read directory {:?}: {e}", dir))?;
+
+    for entry in read_dir {
+        let entry = entry.map_err(|e| format!("Failed to read dir entry: {e}"))?;
+        let path = entry.path();
+
+        if path.is_dir() {
+            remove_empty_dirs(&path)?;
+
+            let is_empty = std::fs::read_dir(&path)
+                .map(|mut d| d.next().is_none())
+                .unwrap_or(false);
+            if is_empty {
+                std::fs::remove_dir(&path)
+                    .map_err(|e| format!("Failed to remove directory {:?}: {e}", path))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Entry points Equicord's build looks for in a plugin folder.
+const PLUGIN_ENTRY_POINTS: &[&str] = &["index.ts", "index.tsx", "index.js", "index.jsx"];
+
+/// Directories of the repo holding built-in plugins, which userplugins must not shadow.
+const BUILTIN_PLUGIN_DIRS: &[&str] = &["plugins", "equicordplugins"];
+
+/// Name a plugin is registered under, i.e. its folder or file name without extension.
+fn plugin_stem(plugin: &str) -> &str {
+    plugin
+        .rsplit_once('.')
+        .filter(|(_, ext)| matches!(*ext, "ts" | "tsx" | "js" | "jsx"))
+        .map_or(plugin, |(stem, _)| stem)
+}
+
+/// Check every plugin that is about to be synced, returning the problems per plugin.
+///
+/// `files` maps paths relative to `src/userplugins/` to the file they are copied from.
+fn validate_plugins(
+    files: &BTreeMap<String, PathBuf>,
+    userplugins_srcs: &[PathBuf],
+    repo_dir: &Path,
+) -> Vec<(String, String)> {
+    let builtin: BTreeSet<String> = BUILTIN_PLUGIN_DIRS
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(repo_dir.join("src").join(dir)).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| plugin_stem(&entry.file_name().to_string_lossy()).to_string())
+        .collect();
+
+    let mut plugins: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for relative in files.keys() {
+        let plugin = plugin_name(relative);
+        let inner = relative[plugin.len()..].trim_start_matches(['/', '\\']);
+        plugins.entry(plugin).or_default().push(inner);
+    }
+
+    let mut invalid = Vec::new();
+    for (plugin, inner_files) in &plugins {
+        // A lone file at the top level is a single-file plugin, or just a README
+        let is_folder = inner_files.iter().any(|inner| !inner.is_empty());
+
+        let problem = if is_folder
+            && !PLUGIN_ENTRY_POINTS
+                .iter()
+                .any(|entry| inner_files.contains(entry))
+        {
+            Some("missing an index.ts or index.tsx entry point".to_string())
+        } else if is_folder
+            && userplugins_srcs
+                .iter()
+                .any(|src| src.join(plugin).join(".git").exists())
+        {
+            Some(
+                "contains a nested .git, copy the plugin or use a git URL source instead"
+                    .to_string(),
+            )
+        } else if (is_folder || plugin_stem(plugin) != plugin)
+            && builtin.contains(plugin_stem(plugin))
+        {
+            Some(format!(
+                "has the same name as a built-in Equicord plugin ({})",
+                plugin_stem(plugin)
+            ))
+        } else {
+            None
+        };
+
+        if let Some(problem) = problem {
+            invalid.push((plugin.clone(), problem));
+        }
+    }
+
+    invalid
+}
+
+/// Work out where every file should come from. Later sources win on conflicts.
+fn wanted_files(userplugins_srcs: &[PathBuf]) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut wanted: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for userplugins_src in userplugins_srcs {
+        let rules = IgnoreRules::load(userplugins_src)?;
+        let mut files = Vec::new();
+        collect_files(userplugins_src, userplugins_src, &rules, &mut files)?;
+
+        for relative in files {
+            let src_path = userplugins_src.join(&relative);
+            if let Some(previous) = wanted.insert(relative.clone(), src_path) {
+                warn!(
+                    "'{relative}' is provided by more than one source, overriding {:?}.",
+                    previous
+                );
+            }
+        }
+    }
+
+    Ok(wanted)
+}
+
+/// Validate the plugins in `wanted`, failing on invalid ones unless `skip_invalid` is set.
+///
+/// Returns the invalid plugins that are to be skipped.
+fn check_plugins(
+    wanted: &BTreeMap<String, PathBuf>,
+    userplugins_srcs: &[PathBuf],
+    repo_dir: &Path,
+    skip_invalid: bool,
+) -> Result<Vec<(String, String)>, String> {
+    let invalid = validate_plugins(wanted, userplugins_srcs, repo_dir);
+    if invalid.is_empty() {
+        return Ok(invalid);
+    }
+
+    let problems = invalid
+        .iter()
+        .map(|(plugin, problem)| format!("  {plugin}: {problem}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if !skip_invalid {
+        return Err(format!(
+            "Found invalid userplugins:\n{problems}\n\
+            Fix them, or pass --skip-invalid-plugins to build without them."
+        ));
+    }
+
+    warn!("skipping invalid userplugins:\n{problems}");
+    Ok(invalid)
+}
+
+/// Validate the userplugins [`sync_userplugins`] would copy, without copying anything.
+///
+/// Returns the invalid plugins that would be skipped.
+pub fn check_userplugins(
+    userplugins_srcs: &[PathBuf],
+    repo_dir: &Path,
+    skip_invalid: bool,
+) -> Result<Vec<(String, String)>, String> {
+    let wanted = wanted_files(userplugins_srcs)?;
+    check_plugins(&wanted, userplugins_srcs, repo_dir, skip_invalid)
+}
+
+/// Sync userplugins from every source into the repo's src/userplugins/ directory,
+/// skipping anything excluded by the source's ignore rules.
+///
+/// Only files whose contents differ are copied and files no longer provided by any
+/// source are deleted, so an unchanged set of plugins leaves the directory untouched.
+///
+/// Plugins are validated first. Invalid ones fail the sync, or are left out of the
+/// build if `skip_invalid` is set.
+pub fn sync_userplugins(
+    userplugins_srcs: &[PathBuf],
+    repo_dir: &Path,
+    skip_invalid: bool,
+) -> Result<SyncReport, String> {
+    let dest = repo_dir.join("src").join("userplugins");
+
+    std::fs::create_dir_all(&dest).map_err(|e| format!("Failed to create userplugins dir: {e}"))?;
+
+    let mut wanted = wanted_files(userplugins_srcs)?;
+    let invalid = check_plugins(&wanted, userplugins_srcs, repo_dir, skip_invalid)?;
+    let skipped: BTreeSet<&String> = invalid.iter().map(|(plugin, _)| plugin).collect();
+    wanted.retain(|relative, _| !skipped.contains(&plugin_name(relative)));
+
+    // Everything currently in the repo is fair game for removal, ignored or not
+    let mut existing = Vec::new();
+    collect_files(&dest, &dest, &IgnoreRules::none(), &mut existing)?;
+    let existing: BTreeSet<String> = existing.into_iter().collect();
+
+    let existing_plugins: BTreeSet<String> = existing.iter().map(|f| plugin_name(f)).collect();
+    let wanted_plugins: BTreeSet<String> = wanted.keys().map(|f| plugin_name(f)).collect();
+    let mut changed_plugins = BTreeSet::new();
+
+    for (relative, src_path) in &wanted {
+        let dest_path = dest.join(relative);
+
+        if existing.contains(relative) && files_equal(src_path, &dest_path)? {
+            continue;
+        }
+
+        if let Some(parent) = dest_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create dir {:?}: {e}", parent))?;
+        }
+
+        std::fs::copy(src_path, &dest_path)
+            .map_err(|e| format!("Failed to copy {:?}: {e}", src_path))?;
+        changed_plugins.insert(plugin_name(relative));
+    }
+
+    for relative in existing.iter().filter(|f| !wanted.contains_key(*f)) {
+        let dest_path = dest.join(relative);
+        std::fs::remove_file(&dest_path)
+            .map_err(|e| format!("Failed to remove {:?}: {e}", dest_path))?;
+        changed_plugins.insert(plugin_name(relative));
+    }
+
+    remove_empty_dirs(&dest)?;
+
+    let mut report = SyncReport {
+        skipped: invalid,
+        ..Default::default()
+    };
+    for plugin in &wanted_plugins {
+        if !existing_plugins.contains(plugin) {
+            report.added.push(plugin.clone());
+        } else if changed_plugins.contains(plugin) {
+            report.changed.push(plugin.clone());
+        } else {
+            report.unchanged += 1;
+        }
+    }
+    report.removed = existing_plugins
+        .difference(&wanted_plugins)
+        .cloned()
+        .collect();
+
+    if report.is_empty() {
+        info!(
+            "Userplugins are up to date ({} plugin(s)).",
+            report.unchanged
+        );
+    } else {
+        info!(
+            "Synced {} userplugin(s): {} added, {} changed, {} removed.",
+            wanted_plugins.len(),
+            report.added.len(),
+            report.changed.len(),
+            report.removed.len()
+        );
+        for (label, plugins) in [
+            ("Added", &report.added),
+            ("Changed", &report.changed),
+            ("Removed", &report.removed),
+        ] {
+            if !plugins.is_empty() {
+                info!("  {label}: {}", plugins.join(", "));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+
+    use super::*;
+
+    /// A scratch directory removed again when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!(
+                "equicord-launcher-test-{name}-{}",
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, relative: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn hash_follows_contents_and_paths() {
+        let temp = TempDir::new("hash");
+        let plugins = temp.0.join("plugins");
+        let cache = temp.0.join("cache");
+        fs::create_dir_all(&cache).unwrap();
+        let dirs = [plugins.clone()];
+
+        temp.write("plugins/myPlugin/index.ts", "export default {};");
+        let first = hash_directories(&dirs, &cache).unwrap();
+        assert_eq!(hash_directories(&dirs, &cache).unwrap(), first);
+
+        temp.write("plugins/myPlugin/index.ts", "export default { name: 1 };");
+        let changed = hash_directories(&dirs, &cache).unwrap();
+        assert_ne!(changed, first);
+
+        fs::rename(plugins.join("myPlugin"), plugins.join("renamed")).unwrap();
+        assert_ne!(hash_directories(&dirs, &cache).unwrap(), changed);
+
+        // Ignored files don't count
+        let renamed = hash_directories(&dirs, &cache).unwrap();
+        temp.write("plugins/renamed/debug.log", "noise");
+        temp.write("plugins/renamed/node_modules/dep/index.js", "noise");
+        assert_eq!(hash_directories(&dirs, &cache).unwrap(), renamed);
+    }
+
+    #[test]
+    fn missing_directories_hash_as_empty() {
+        let temp = TempDir::new("hash-empty");
+        let dirs = [temp.0.join("missing")];
+
+        assert_eq!(hash_directories(&dirs, &temp.0).unwrap(), "empty");
+    }
+
+    #[test]
+    fn peeking_leaves_the_cache_alone() {
+        let temp = TempDir::new("hash-peek");
+        let dirs = [temp.0.join("plugins")];
+        let cache_file = temp.0.join(constants::USERPLUGINS_HASH_CACHE_FILE);
+        temp.write("plugins/myPlugin.ts", "export default {};");
+
+        let peeked = peek_directories_hash(&dirs, &temp.0).unwrap();
+        assert!(!cache_file.exists());
+
+        assert_eq!(hash_directories(&dirs, &temp.0).unwrap(), peeked);
+        assert!(cache_file.exists());
+    }
+
+    #[test]
+    fn cached_hashes_are_reused_until_the_stamp_changes() {
+        let temp = TempDir::new("hash-cache");
+        let path = temp.write("index.ts", "export default {};");
+
+        // Old enough to be outside the racy window
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+
+        let mut cache = HashMap::new();
+        let real = hash_file(&path, &HashMap::new(), &mut cache).unwrap();
+        assert_eq!(real.len(), 64);
+
+        // Pretend the cached hash differs, to see whether it's used
+        let key = path.to_string_lossy().to_string();
+        let old_cache = HashMap::from([(
+            key.clone(),
+            CachedHash {
+                stamp: cache[&key].stamp.clone(),
+                hash: "cached".to_string(),
+            },
+        )]);
+        assert_eq!(
+            hash_file(&path, &old_cache, &mut HashMap::new()).unwrap(),
+            "cached"
+        );
+
+        temp.write("index.ts", "export default { changed: true };");
+        let mut new_cache = HashMap::new();
+        let rehashed = hash_file(&path, &old_cache, &mut new_cache).unwrap();
+        assert_ne!(rehashed, "cached");
+        assert_ne!(rehashed, real);
+        // Just written, so too recent to be trusted next time
+        assert!(!new_cache.contains_key(&key));
+    }
+
+    /// Validate everything in `src`, as syncing it into `repo` would.
+    fn problems(src: &Path, repo: &Path) -> Vec<(String, String)> {
+        let srcs = [src.to_path_buf()];
+        validate_plugins(&wanted_files(&srcs).unwrap(), &srcs, repo)
+    }
+
+    #[test]
+    fn valid_plugins_pass() {
+        let temp = TempDir::new("validate-ok");
+        temp.write("repo/src/plugins/messageLogger/index.ts", "");
+        temp.write("plugins/folderPlugin/index.tsx", "");
+        temp.write("plugins/folderPlugin/styles.css", "");
+        temp.write("plugins/singleFile.ts", "");
+        temp.write("plugins/README.md", "");
+
+        assert!(problems(&temp.0.join("plugins"), &temp.0.join("repo")).is_empty());
+    }
+
+    #[test]
+    fn invalid_plugins_are_reported() {
+        let temp = TempDir::new("validate-invalid");
+        temp.write("repo/src/plugins/messageLogger/index.ts", "");
+        temp.write("repo/src/equicordplugins/petpet.tsx", "");
+        temp.write("plugins/noEntry/utils.ts", "");
+        temp.write("plugins/nested/index.ts", "");
+        temp.write("plugins/nested/.git/HEAD", "");
+        temp.write("plugins/messageLogger/index.ts", "");
+        temp.write("plugins/petpet.ts", "");
+
+        let problems = problems(&temp.0.join("plugins"), &temp.0.join("repo"));
+        let names: Vec<&str> = problems.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["messageLogger", "nested", "noEntry", "petpet.ts"]);
+
+        assert!(problems[0].1.contains("built-in"));
+        assert!(problems[1].1.contains("nested .git"));
+        assert!(problems[2].1.contains("entry point"));
+        assert!(problems[3].1.contains("built-in Equicord plugin (petpet)"));
+    }
+
+    #[test]
+    fn invalid_plugins_fail_unless_skipped() {
+        let temp = TempDir::new("validate-skip");
+        temp.write("plugins/noEntry/utils.ts", "");
+        temp.write("plugins/good/index.ts", "");
+        let srcs = [temp.0.join("plugins")];
+        let repo = temp.0.join("repo");
+
+        let error = check_userplugins(&srcs, &repo, false).unwrap_err();
+        assert!(error.contains("noEntry: missing an index.ts"));
+        assert!(error.contains("--skip-invalid-plugins"));
+
+        let report = sync_userplugins(&srcs, &repo, true).unwrap();
+        let skipped: Vec<&str> = report
+            .skipped
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(skipped, ["noEntry"]);
+        assert!(repo.join("src/userplugins/good/index.ts").is_file());
+        assert!(!repo.join("src/userplugins/noEntry").exists());
+    }
+
+    /// Every file under `dir` with its contents and modification time.
+    fn snapshot(dir: &Path) -> BTreeMap<String, (Vec<u8>, SystemTime)> {
+        let mut files = Vec::new();
+        collect_files(dir, dir, &IgnoreRules::none(), &mut files).unwrap();
+        files
+            .into_iter()
+            .map(|relative| {
+                let path = dir.join(&relative);
+                let modified = fs::metadata(&path).unwrap().modified().unwrap();
+                (relative, (fs::read(&path).unwrap(), modified))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_sync_leaves_the_repo_untouched() {
+        let temp = TempDir::new("sync-unchanged");
+        temp.write("plugins/folderPlugin/index.ts", "export default {};");
+        temp.write(
+            "plugins/folderPlugin/utils/helpers.ts",
+            "export const x = 1;",
+        );
+        temp.write("plugins/singleFile.ts", "export default {};");
+        let srcs = [temp.0.join("plugins")];
+        let repo = temp.0.join("repo");
+        let dest = repo.join("src").join("userplugins");
+
+        let first = sync_userplugins(&srcs, &repo, false).unwrap();
+        assert_eq!(first.added, ["folderPlugin", "singleFile.ts"]);
+
+        // Backdate the synced files, so any rewrite would show up in the mtimes
+        for entry in snapshot(&dest).keys() {
+            File::options()
+                .write(true)
+                .open(dest.join(entry))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(3600))
+                .unwrap();
+        }
+        let before = snapshot(&dest);
+
+        let second = sync_userplugins(&srcs, &repo, false).unwrap();
+        assert!(second.is_empty());
+        assert_eq!(second.unchanged, 2);
+        assert_eq!(snapshot(&dest), before);
+    }
+
+    #[test]
+    fn sync_reports_added_changed_and_removed_plugins() {
+        let temp = TempDir::new("sync-report");
+        temp.write("plugins/kept/index.ts", "export default {};");
+        temp.write("plugins/edited/index.ts", "export default {};");
+        temp.write("plugins/deleted/index.ts", "export default {};");
+        temp.write("plugins/deleted/nested/deep.ts", "");
+        temp.write("plugins/shrunk/index.ts", "export default {};");
+        temp.write("plugins/shrunk/extra/file.ts", "");
+        let srcs = [temp.0.join("plugins")];
+        let repo = temp.0.join("repo");
+        let dest = repo.join("src").join("userplugins");
+
+        sync_userplugins(&srcs, &repo, false).unwrap();
+
+        temp.write(
+            "plugins/edited/index.ts",
+            "export default { edited: true };",
+        );
+        temp.write("plugins/added.tsx", "export default {};");
+        fs::remove_dir_all(temp.0.join("plugins/deleted")).unwrap();
+        fs::remove_dir_all(temp.0.join("plugins/shrunk/extra")).unwrap();
+
+        let report = sync_userplugins(&srcs, &repo, false).unwrap();
+        assert_eq!(report.added, ["added.tsx"]);
+        assert_eq!(report.changed, ["edited", "shrunk"]);
+        assert_eq!(report.removed, ["deleted"]);
+        assert_eq!(report.unchanged, 1);
+
+        assert_eq!(
+            fs::read_to_string(dest.join("edited/index.ts")).unwrap(),
+            "export default { edited: true };"
+        );
+        assert!(dest.join("added.tsx").is_file());
+        // Deleted files go, and so do the directories they leave empty
+        assert!(!dest.join("deleted").exists());
+        assert!(!dest.join("shrunk/extra").exists());
+        assert!(dest.join("shrunk/index.ts").is_file());
+    }
+}
@@ -0,0 +1,371 @@
+//! Minimal version parsing and range matching for build prerequisites.
+//!
+//! Understands the subset of npm's semver ranges used in `engines` fields:
+//! comparators (`>=18`, `<21.0.0`), caret and tilde ranges, `x` wildcards,
+//! hyphen ranges (`18 - 20`), space-separated intersections and `||` unions.
+
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// Find the first version number in a tool's `--version` output,
+    /// e.g. `git version 2.43.0`, `v20.11.1` or `10.4.1`.
+    pub fn find_in(output: &str) -> Option<Version> {
+        output
+            .split(|c: char| c.is_whitespace() || c == '@')
+            .map(|word| word.trim_start_matches('v'))
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+            .and_then(|word| Version::parse(word).ok())
+    }
+
+    /// Parse `1`, `1.2` or `1.2.3`, ignoring any prerelease or build suffix.
+    pub fn parse(version: &str) -> Result<Version, String> {
+        let (version, _) = parse_partial(version)?;
+        Ok(version)
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Parse a possibly partial version, returning how many components were given.
+/// Missing and wildcard components count as 0.
+fn parse_partial(version: &str) -> Result<(Version, usize), String> {
+    let trimmed = version.trim().trim_start_matches(['v', '=']);
+    let core = trimmed.split(['-', '+']).next().unwrap_or(trimmed);
+
+    let mut parts = [0u64; 3];
+    let mut given = 0;
+
+    for (i, part) in core.split('.').take(3).enumerate() {
+        if matches!(part, "x" | "X" | "*") {
+            break;
+        }
+
+        parts[i] = part
+            .parse()
+            .map_err(|_| format!("Invalid version '{version}'"))?;
+        given = i + 1;
+    }
+
+    let version = Version {
+        major: parts[0],
+        minor: parts[1],
+        patch: parts[2],
+    };
+
+    Ok((version, given))
+}
+
+/// The smallest version greater than every version matching the first `given` components.
+fn bump(version: Version, given: usize) -> Version {
+    match given {
+        0 => Version {
+            major: u64::MAX,
+            minor: 0,
+            patch: 0,
+        },
+        1 => Version {
+            major: version.major + 1,
+            minor: 0,
+            patch: 0,
+        },
+        2 => Version {
+            minor: version.minor + 1,
+            patch: 0,
+            ..version
+        },
+        _ => Version {
+            patch: version.patch + 1,
+            ..version
+        },
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Comparator {
+    ordering: &'static [Ordering],
+    version: Version,
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        self.ordering.contains(&version.cmp(&self.version))
+    }
+}
+
+const GREATER_OR_EQUAL: &[Ordering] = &[Ordering::Greater, Ordering::Equal];
+const LESS: &[Ordering] = &[Ordering::Less];
+
+/// A version range such as `>=18`, `^9.1.0` or `>=18.12 <21 || >=22`.
+#[derive(Clone, Debug)]
+pub struct VersionReq {
+    source: String,
+    /// Any of these sets must match, where a set matches if all its comparators do.
+    alternatives: Vec<Vec<Comparator>>,
+}
+
+impl VersionReq {
+    pub fn parse(range: &str) -> Result<VersionReq, String> {
+        let mut alternatives = Vec::new();
+
+        for alternative in range.split("||") {
+            let mut comparators = Vec::new();
+
+            // Allow whitespace between an operator and its version, as in `>= 18`
+            let mut parts: Vec<String> = Vec::new();
+            let mut pending_operator = String::new();
+            for part in alternative.split_whitespace() {
+                if part
+                    .chars()
+                    .all(|c| matches!(c, '<' | '>' | '=' | '^' | '~'))
+                {
+                    pending_operator.push_str(part);
+                } else {
+                    parts.push(format!("{pending_operator}{part}"));
+                    pending_operator.clear();
+                }
+            }
+
+            let mut parts = parts.iter().map(String::as_str).peekable();
+            while let Some(part) = parts.next() {
+                let result = if parts.next_if_eq(&"-").is_some() {
+                    match parts.next() {
+                        Some(upper) => parse_hyphen_range(part, upper, &mut comparators),
+                        None => Err(format!("Missing upper bound after '{part} -'")),
+                    }
+                } else {
+                    parse_comparator(part, &mut comparators)
+                };
+
+                result.map_err(|e| format!("Invalid version range '{range}': {e}"))?;
+            }
+
+            alternatives.push(comparators);
+        }
+
+        Ok(VersionReq {
+            source: range.trim().to_string(),
+            alternatives,
+        })
+    }
+
+    /// A `>=` requirement on a single version.
+    pub fn at_least(version: Version) -> VersionReq {
+        VersionReq {
+            source: format!(">={version}"),
+            alternatives: vec![vec![Comparator {
+                ordering: GREATER_OR_EQUAL,
+                version,
+            }]],
+        }
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives
+            .iter()
+            .any(|comparators| comparators.iter().all(|c| c.matches(version)))
+    }
+}
+
+impl std::fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Parse `lower - upper`, where a partial `upper` allows everything it matches,
+/// e.g. `1.2 - 2.3` is `>=1.2.0 <2.4.0`.
+fn parse_hyphen_range(
+    lower: &str,
+    upper: &str,
+    comparators: &mut Vec<Comparator>,
+) -> Result<(), String> {
+    let (lower, _) = parse_partial(lower)?;
+    let (upper, given) = parse_partial(upper)?;
+
+    comparators.push(Comparator {
+        ordering: GREATER_OR_EQUAL,
+        version: lower,
+    });
+    if given > 0 {
+        comparators.push(Comparator {
+            ordering: LESS,
+            version: bump(upper, given),
+        });
+    }
+
+    Ok(())
+}
+
+fn parse_comparator(part: &str, comparators: &mut Vec<Comparator>) -> Result<(), String> {
+    let mut range = |lower: Version, upper: Version| {
+        comparators.push(Comparator {
+            ordering: GREATER_OR_EQUAL,
+            version: lower,
+        });
+        comparators.push(Comparator {
+            ordering: LESS,
+            version: upper,
+        });
+    };
+
+    if let Some(rest) = part.strip_prefix(">=") {
+        let (version, _) = parse_partial(rest)?;
+        comparators.push(Comparator {
+            ordering: GREATER_OR_EQUAL,
+            version,
+        });
+    } else if let Some(rest) = part.strip_prefix("<=") {
+        let (version, given) = parse_partial(rest)?;
+        // `<=1.2` allows every 1.2.x
+        comparators.push(Comparator {
+            ordering: LESS,
+            version: bump(version, given),
+        });
+    } else if let Some(rest) = part.strip_prefix('>') {
+        let (version, given) = parse_partial(rest)?;
+        // `>1.2` excludes every 1.2.x
+        comparators.push(Comparator {
+            ordering: GREATER_OR_EQUAL,
+            version: bump(version, given),
+        });
+    } else if let Some(rest) = part.strip_prefix('<') {
+        let (version, _) = parse_partial(rest)?;
+        comparators.push(Comparator {
+            ordering: LESS,
+            version,
+        });
+    } else if let Some(rest) = part.strip_prefix('^') {
+        let (version, given) = parse_partial(rest)?;
+        // Allow changes that don't modify the left-most non-zero component
+        let upper = if version.major > 0 || given < 2 {
+            bump(version, 1)
+        } else if version.minor > 0 || given < 3 {
+            bump(version, 2)
+        } else {
+            bump(version, 3)
+        };
+        range(version, upper);
+    } else if let Some(rest) = part.strip_prefix('~') {
+        let (version, given) = parse_partial(rest)?;
+        range(version, bump(version, given.min(2)));
+    } else {
+        let (version, given) = parse_partial(part)?;
+        if given > 0 {
+            range(version, bump(version, given));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    fn matches(range: &str, version: &str) -> bool {
+        VersionReq::parse(range).unwrap().matches(&v(version))
+    }
+
+    #[test]
+    fn finds_versions_in_tool_output() {
+        assert_eq!(Version::find_in("git version 2.43.0"), Some(v("2.43.0")));
+        assert_eq!(Version::find_in("v20.11.1\n"), Some(v("20.11.1")));
+        assert_eq!(Version::find_in("corepack pnpm@10.4.1"), Some(v("10.4.1")));
+        assert_eq!(Version::find_in("no version here"), None);
+    }
+
+    #[test]
+    fn parses_partial_and_prerelease_versions() {
+        assert_eq!(v("18"), v("18.0.0"));
+        assert_eq!(v("v1.2"), v("1.2.0"));
+        assert_eq!(v("1.2.3-beta.1+build"), v("1.2.3"));
+        assert!(Version::parse("abc").is_err());
+    }
+
+    #[test]
+    fn comparators() {
+        assert!(matches(">=18", "18.0.0"));
+        assert!(!matches(">=18", "17.9.9"));
+        assert!(matches(">= 18.12", "18.12.0"));
+        assert!(matches("<21", "20.99.0"));
+        assert!(!matches("<21", "21.0.0"));
+        assert!(matches("<=1.2", "1.2.9"));
+        assert!(!matches("<=1.2", "1.3.0"));
+        assert!(!matches(">1.2", "1.2.9"));
+        assert!(matches(">1.2", "1.3.0"));
+        assert!(matches("=1.2.3", "1.2.3"));
+        assert!(!matches("1.2.3", "1.2.4"));
+    }
+
+    #[test]
+    fn caret_and_tilde_ranges() {
+        assert!(matches("^9.1.0", "9.15.0"));
+        assert!(!matches("^9.1.0", "10.0.0"));
+        assert!(!matches("^9.1.0", "9.0.9"));
+        assert!(matches("^0.2.3", "0.2.9"));
+        assert!(!matches("^0.2.3", "0.3.0"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+        assert!(matches("~1", "1.9.0"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", "0.0.1"));
+        assert!(matches("", "99.0.0"));
+        assert!(matches("18.x", "18.20.0"));
+        assert!(!matches("18.x", "19.0.0"));
+        assert!(matches("1.2.X", "1.2.7"));
+    }
+
+    #[test]
+    fn hyphen_ranges() {
+        assert!(matches("1.2.3 - 2.3.4", "1.2.3"));
+        assert!(matches("1.2.3 - 2.3.4", "2.3.4"));
+        assert!(!matches("1.2.3 - 2.3.4", "2.3.5"));
+        assert!(!matches("1.2.3 - 2.3.4", "1.2.2"));
+        assert!(matches("1.2 - 2.3", "2.3.9"));
+        assert!(!matches("1.2 - 2.3", "2.4.0"));
+        assert!(matches("18 - 20", "20.11.1"));
+        assert!(!matches("18 - 20", "21.0.0"));
+        assert!(matches("1 - 2 || >=4", "4.1.0"));
+        assert!(!matches("1 - 2 || >=4", "3.0.0"));
+    }
+
+    #[test]
+    fn intersections_and_unions() {
+        assert!(matches(">=18.12 <21 || >=22", "20.1.0"));
+        assert!(!matches(">=18.12 <21 || >=22", "21.5.0"));
+        assert!(matches(">=18.12 <21 || >=22", "22.0.0"));
+        assert!(!matches(">=18.12 <21 || >=22", "18.11.0"));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!(VersionReq::parse(">=abc").is_err());
+        assert!(VersionReq::parse("1.2.3 -").is_err());
+        assert!(VersionReq::parse("1.2.3 - abc").is_err());
+    }
+
+    #[test]
+    fn displays_the_original_range() {
+        assert_eq!(VersionReq::parse(" >=18 ").unwrap().to_string(), ">=18");
+        assert_eq!(VersionReq::at_least(v("9.0.0")).to_string(), ">=9.0.0");
+    }
+}
@@ -0,0 +1,125 @@
+//! Watch mode for custom builds.
+//!
+//! Polls the local userplugin directories and, once they settle after a change,
+//! reruns the custom build and restarts Discord with the new bundle.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use crate::builder::{self, BuildOptions, BuildOutcome};
+use crate::events::{self, Event};
+use crate::lock::CacheLock;
+use crate::sources::UserpluginSource;
+use crate::userplugins::peek_directories_hash;
+use crate::{build_log, constants};
+
+/// How often the userplugin directories are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the directories must stay unchanged before a rebuild starts,
+/// so that saving many files at once only triggers a single build.
+const DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// Launch Discord with `start`, then rebuild and restart it on every userplugin change.
+///
+/// `start` returns the process ID of the launched instance, which is handed to
+/// `stop` before the next launch. `rebuilt` gets the outcome of every successful
+/// rebuild, before Discord is restarted. Runs until the launcher is killed.
+pub fn watch(
+    options: &BuildOptions,
+    mut start: impl FnMut() -> Result<Option<u32>, String>,
+    mut stop: impl FnMut(u32),
+    mut rebuilt: impl FnMut(&BuildOutcome),
+) -> Result<(), String> {
+    // Git sources only change when fetched, so there is nothing to watch there
+    let dirs: Vec<PathBuf> = options
+        .userplugin_sources
+        .iter()
+        .filter_map(|source| match UserpluginSource::parse(source) {
+            UserpluginSource::Local(path) => Some(path),
+            UserpluginSource::Git { .. } => None,
+        })
+        .collect();
+
+    if dirs.is_empty() {
+        return Err("--watch needs at least one local --custom directory".into());
+    }
+
+    let cache_dir = constants::asset_cache_dir()
+        .ok_or_else(|| "Failed to determine asset cache directory".to_string())?;
+
+    let rebuild_options = BuildOptions {
+        update_repos: false,
+        ..options.clone()
+    };
+
+    // Only the build saves the hash cache, under the cache lock, so polling just reads it
+    let mut pid = start()?;
+    let mut last_hash = peek_directories_hash(&dirs, &cache_dir)?;
+
+    info!(
+        "Watching {} userplugin director{} for changes...",
+        dirs.len(),
+        if dirs.len() == 1 { "y" } else { "ies" }
+    );
+
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+
+        // Files can briefly disappear while an editor saves them, just try again next time
+        let Ok(hash) = peek_directories_hash(&dirs, &cache_dir) else {
+            continue;
+        };
+
+        if hash == last_hash {
+            continue;
+        }
+
+        // Wait for the changes to settle
+        let mut settled = hash;
+        loop {
+            std::thread::sleep(DEBOUNCE);
+            match peek_directories_hash(&dirs, &cache_dir) {
+                Ok(hash) if hash == settled => break,
+                Ok(hash) => settled = hash,
+                Err(_) => continue,
+            }
+        }
+        last_hash = settled;
+
+        info!("Userplugins changed, rebuilding...");
+
+        let build_result = CacheLock::acquire()
+            .and_then(|_cache_lock| builder::run_custom_build(&rebuild_options));
+
+        let outcome = match build_result {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                error!("Rebuild failed: {e}");
+                events::emit(Event::BuildFinished {
+                    error: Some(&e),
+                    dropped_plugins: &[],
+                    log_path: build_log::latest_build_log().as_deref(),
+                });
+                warn!("Keeping the current Discord instance running.");
+                continue;
+            }
+        };
+
+        events::emit(Event::BuildFinished {
+            error: None,
+            dropped_plugins: &outcome.dropped_plugins,
+            log_path: outcome.log_path.as_deref(),
+        });
+        rebuilt(&outcome);
+
+        info!("Rebuild succeeded, restarting Discord...");
+
+        if let Some(pid) = pid.take() {
+            stop(pid);
+        }
+        pid = start()?;
+    }
+}
@@ -32,6 +32,16 @@ pub fn messagebox(title: &str, body: &str, icon: MessageBoxIcon) {
     }
 }
 
+/// Attach to the console of the parent process, if any, so that output of
+/// subcommands shows up when the launcher is run from a terminal.
+pub fn attach_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 pub fn get_latest_executable(dir: &std::path::Path) -> Result<std::path::PathBuf, String> {
     let dir_name = dir
         .file_name()
@@ -0,0 +1,246 @@
+//! Minimal writer for deflate-compressed zip archives, enough for support bundles.
+//!
+//! Doesn't support zip64, so archives and entries must stay below 4 GiB.
+
+use std::io::{self, Write};
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::build_log::UtcTime;
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+/// Version 2.0, the first with deflate.
+const VERSION: u16 = 20;
+/// File names are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_DEFLATE: u16 = 8;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub struct ZipWriter<W: Write> {
+    out: W,
+    offset: u32,
+    entries: Vec<Entry>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "zip archive larger than 4 GiB")
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Start an archive whose entries are all timestamped with `time`.
+    pub fn new(out: W, time: UtcTime) -> ZipWriter<W> {
+        ZipWriter {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+            dos_time: ((time.hour << 11) | (time.minute << 5) | (time.second / 2)) as u16,
+            dos_date: ((time.year.saturating_sub(1980) << 9) | (time.month << 5) | time.day) as u16,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|len| self.offset.checked_add(len))
+            .ok_or_else(too_large)?;
+        Ok(())
+    }
+
+    /// Add a file named `name`, using `/` as the path separator.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            compressed_size: u32::try_from(compressed.len()).map_err(|_| too_large())?,
+            size: u32::try_from(data.len()).map_err(|_| too_large())?,
+            offset: self.offset,
+        };
+
+        let mut header = Vec::new();
+        header.extend(LOCAL_FILE_HEADER.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        header.extend(FLAG_UTF8.to_le_bytes());
+        header.extend(METHOD_DEFLATE.to_le_bytes());
+        header.extend(self.dos_time.to_le_bytes());
+        header.extend(self.dos_date.to_le_bytes());
+        header.extend(entry.crc.to_le_bytes());
+        header.extend(entry.compressed_size.to_le_bytes());
+        header.extend(entry.size.to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes()); // extra field length
+        header.extend(name.as_bytes());
+
+        self.write(&header)?;
+        self.write(&compressed)?;
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the central directory and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let central_directory_offset = self.offset;
+
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            directory.extend(CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+            directory.extend(VERSION.to_le_bytes()); // version made by
+            directory.extend(VERSION.to_le_bytes()); // version needed
+            directory.extend(FLAG_UTF8.to_le_bytes());
+            directory.extend(METHOD_DEFLATE.to_le_bytes());
+            directory.extend(self.dos_time.to_le_bytes());
+            directory.extend(self.dos_date.to_le_bytes());
+            directory.extend(entry.crc.to_le_bytes());
+            directory.extend(entry.compressed_size.to_le_bytes());
+            directory.extend(entry.size.to_le_bytes());
+            directory.extend((entry.name.len() as u16).to_le_bytes());
+            directory.extend(0u16.to_le_bytes()); // extra field length
+            directory.extend(0u16.to_le_bytes()); // comment length
+            directory.extend(0u16.to_le_bytes()); // disk number
+            directory.extend(0u16.to_le_bytes()); // internal attributes
+            directory.extend(0u32.to_le_bytes()); // external attributes
+            directory.extend(entry.offset.to_le_bytes());
+            directory.extend(entry.name.as_bytes());
+        }
+        self.write(&directory)?;
+
+        let entries = self.entries.len() as u16;
+        let mut end = Vec::new();
+        end.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // this disk
+        end.extend(0u16.to_le_bytes()); // disk with the central directory
+        end.extend(entries.to_le_bytes());
+        end.extend(entries.to_le_bytes());
+        end.extend((directory.len() as u32).to_le_bytes());
+        end.extend(central_directory_offset.to_le_bytes());
+        end.extend(0u16.to_le_bytes()); // comment length
+        self.write(&end)?;
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::DeflateDecoder;
+
+    use super::*;
+
+    const TIME: UtcTime = UtcTime {
+        year: 2025,
+        month: 3,
+        day: 14,
+        hour: 15,
+        minute: 9,
+        second: 26,
+    };
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Read the archive back the way unzip does, starting from the central directory
+    /// and following it to every local header.
+    fn read_archive(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), END_OF_CENTRAL_DIRECTORY);
+        let count = u16_at(archive, end + 10);
+        assert_eq!(u16_at(archive, end + 8), count);
+        let directory_size = u32_at(archive, end + 12) as usize;
+        let mut offset = u32_at(archive, end + 16) as usize;
+        assert_eq!(offset + directory_size, end);
+
+        let mut files = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(archive, offset), CENTRAL_DIRECTORY_HEADER);
+            assert_eq!(u16_at(archive, offset + 10), METHOD_DEFLATE);
+            let crc = u32_at(archive, offset + 16);
+            let compressed_size = u32_at(archive, offset + 20) as usize;
+            let size = u32_at(archive, offset + 24) as usize;
+            let name_len = u16_at(archive, offset + 28) as usize;
+            let local = u32_at(archive, offset + 42) as usize;
+            let name = &archive[offset + 46..offset + 46 + name_len];
+
+            // The local header must agree with the central directory
+            assert_eq!(u32_at(archive, local), LOCAL_FILE_HEADER);
+            assert_eq!(u32_at(archive, local + 14), crc);
+            assert_eq!(u32_at(archive, local + 18) as usize, compressed_size);
+            assert_eq!(u32_at(archive, local + 22) as usize, size);
+            assert_eq!(&archive[local + 30..local + 30 + name_len], name);
+
+            let data_start = local + 30 + name_len + u16_at(archive, local + 28) as usize;
+            let mut data = Vec::new();
+            DeflateDecoder::new(&archive[data_start..data_start + compressed_size])
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data.len(), size);
+            assert_eq!(crc32fast::hash(&data), crc);
+
+            files.push((String::from_utf8(name.to_vec()).unwrap(), data));
+            offset += 46 + name_len;
+        }
+
+        files
+    }
+
+    #[test]
+    fn round_trip() {
+        let log = "launcher log\n".repeat(1000);
+        let mut zip = ZipWriter::new(Vec::new(), TIME);
+        zip.add("info.txt", b"Launcher version: 1.0.0").unwrap();
+        zip.add("logs/launcher.log", log.as_bytes()).unwrap();
+        zip.add("empty.txt", b"").unwrap();
+        zip.add("ünïcode.txt", "ünïcode".as_bytes()).unwrap();
+        let archive = zip.finish().unwrap();
+
+        let files = read_archive(&archive);
+        assert_eq!(
+            files,
+            [
+                ("info.txt".to_string(), b"Launcher version: 1.0.0".to_vec()),
+                ("logs/launcher.log".to_string(), log.into_bytes()),
+                ("empty.txt".to_string(), Vec::new()),
+                ("ünïcode.txt".to_string(), "ünïcode".as_bytes().to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_archive() {
+        let archive = ZipWriter::new(Vec::new(), TIME).finish().unwrap();
+        assert_eq!(archive.len(), 22);
+        assert!(read_archive(&archive).is_empty());
+    }
+
+    #[test]
+    fn dos_timestamp() {
+        let zip = ZipWriter::new(Vec::new(), TIME);
+        assert_eq!(zip.dos_date, (45 << 9) | (3 << 5) | 14);
+        assert_eq!(zip.dos_time, (15 << 11) | (9 << 5) | 13);
+    }
+}